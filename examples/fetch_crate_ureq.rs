@@ -0,0 +1,20 @@
+//!
+//! command to run:<br>
+//! cargo run --example fetch_crate_ureq -F sparse-ureq
+//!
+use crates_index::SparseIndex;
+
+const CRATE_TO_FETCH: &str = "inferno";
+
+fn main() {
+    let index = SparseIndex::new_cargo_default().unwrap();
+
+    match index.fetch_crate(CRATE_TO_FETCH).unwrap() {
+        Some(krate) => {
+            println!("{:?}", krate.highest_normal_version().unwrap().version());
+        }
+        None => {
+            println!("could not find crate {}", CRATE_TO_FETCH)
+        }
+    }
+}
@@ -7,7 +7,9 @@ use crates_index::{Crate, Names, SparseIndex};
 use std::error::Error;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let sparse_index = SparseIndex::new_cargo_default()?;
+    let mut sparse_index = SparseIndex::new_cargo_default()?;
+    // Don't ask for names that were found not to exist in the last hour
+    sparse_index.set_negative_cache_ttl(Some(std::time::Duration::from_secs(60 * 60)));
     let mut count = 0;
     let mut missing = Vec::new();
     for name in std::env::args().skip(1) {
@@ -75,12 +77,19 @@ fn names(name: &str) -> Result<impl Iterator<Item = String>, Box<dyn Error>> {
 /// Create a request to the sparse `index` and parse the response with the side-effect of yielding
 /// the desired crate and updating the local cache.
 fn update_cache(name: &str, index: &SparseIndex) -> Result<Option<Crate>, Box<dyn Error>> {
-    let request = index
-        .make_cache_request(name)?
-        .version(ureq::http::Version::HTTP_11)
-        .body(())?;
+    let request = match index.make_cache_request(name) {
+        Ok(request) => request,
+        Err(crates_index::Error::KnownMissing { .. }) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let request = request.version(ureq::http::Version::HTTP_11).body(())?;
 
-    let response = ureq::run(request)?;
+    // A missing crate is part of the protocol, and needs to be handed to `parse_cache_response()`
+    let agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .new_agent();
+    let response = agent.run(request)?;
 
     let (parts, mut body) = response.into_parts();
     let response = http::Response::from_parts(parts, body.read_to_vec()?);
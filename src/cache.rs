@@ -0,0 +1,358 @@
+#![allow(clippy::result_large_err)]
+
+use std::fs::ReadDir;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The directory holding cache entries, relative to the root of an index.
+pub(crate) const CACHE_DIR: &str = ".cache";
+
+/// The directory holding markers of crates the registry reported as missing, relative to the root of an index.
+///
+/// Cargo doesn't know about it, so it's kept separate from its cache entries.
+pub(crate) const MISSING_DIR: &str = ".missing";
+
+/// The version of cache entries written by cargo 1.54.0 and later.
+pub(crate) const CURRENT_CACHE_VERSION: u8 = 3;
+/// The version of the index format which is written into current cache entries.
+pub(crate) const CURRENT_INDEX_FORMAT_VERSION: u32 = 2;
+
+/// The parts of a cache entry, without validation of the versions.
+///
+/// See src/cargo/sources/registry/index.rs
+pub(crate) struct Header<'a> {
+    pub(crate) cache_version: u8,
+    /// Only present for cache versions 2 and later.
+    pub(crate) index_format_version: Option<u32>,
+    /// The version of the index the entry was created from, i.e. an etag, last-modified date or commit.
+    pub(crate) version: &'a [u8],
+    /// Pairs of semver versions and JSON index lines, separated by null bytes.
+    pub(crate) entries: &'a [u8],
+}
+
+impl<'a> Header<'a> {
+    pub(crate) fn parse(bytes: &'a [u8]) -> io::Result<Self> {
+        let (&cache_version, mut rest) = bytes.split_first().ok_or(io::ErrorKind::UnexpectedEof)?;
+        let index_format_version = match cache_version {
+            1 => None,
+            2 | CURRENT_CACHE_VERSION => {
+                let index_v_bytes = rest.get(..4).ok_or(io::ErrorKind::UnexpectedEof)?;
+                rest = &rest[4..];
+                Some(u32::from_le_bytes(index_v_bytes.try_into().unwrap()))
+            }
+            version => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("cache version '{version}' not currently supported"),
+                ));
+            }
+        };
+        let version_end = memchr::memchr(0, rest).ok_or(io::ErrorKind::UnexpectedEof)?;
+        Ok(Self {
+            cache_version,
+            index_format_version,
+            version: &rest[..version_end],
+            entries: &rest[version_end + 1..],
+        })
+    }
+}
+
+/// Information about an entry in the local cache of an index, see [`SparseIndex::cache_entry_info()`](crate::SparseIndex::cache_entry_info).
+#[derive(Debug, Clone)]
+pub struct CacheEntryInfo {
+    version: String,
+    modified: SystemTime,
+    cache_version: u8,
+    index_format_version: Option<u32>,
+}
+
+impl CacheEntryInfo {
+    pub(crate) fn read(path: &Path) -> io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let modified = std::fs::metadata(path)?.modified()?;
+        let header = Header::parse(&bytes)?;
+        Ok(Self {
+            version: String::from_utf8_lossy(header.version).into_owned(),
+            modified,
+            cache_version: header.cache_version,
+            index_format_version: header.index_format_version,
+        })
+    }
+
+    /// The version of the index the entry was created from, of the form `key: value`.
+    ///
+    /// For sparse indices, the key is either `etag` or `last-modified`, and this version is used to
+    /// revalidate the entry with the server. For git indices, it's the commit the entry was read from.
+    #[inline]
+    #[must_use]
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// The `ETag` of the response the entry was created from, if the server sent one.
+    #[must_use]
+    pub fn etag(&self) -> Option<&str> {
+        self.validator("etag")
+    }
+
+    /// The `Last-Modified` date of the response the entry was created from, if the server sent one
+    /// and there was no `ETag`.
+    #[must_use]
+    pub fn last_modified(&self) -> Option<&str> {
+        self.validator("last-modified")
+    }
+
+    fn validator(&self, name: &str) -> Option<&str> {
+        let (key, value) = self.version.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    }
+
+    /// The time at which the entry was last written, or revalidated with the server.
+    #[inline]
+    #[must_use]
+    pub fn modified(&self) -> SystemTime {
+        self.modified
+    }
+
+    /// The time that passed since the entry was last written or revalidated,
+    /// which is zero if the modification time is in the future.
+    #[must_use]
+    pub fn age(&self) -> Duration {
+        self.modified.elapsed().unwrap_or_default()
+    }
+
+    /// The version of the format of the cache entry itself.
+    #[inline]
+    #[must_use]
+    pub fn cache_version(&self) -> u8 {
+        self.cache_version
+    }
+
+    /// The version of the index format the entry was written for, which isn't recorded by very old versions of cargo.
+    #[inline]
+    #[must_use]
+    pub fn index_format_version(&self) -> Option<u32> {
+        self.index_format_version
+    }
+
+    /// Returns `true` if the entry has the format that cargo currently writes, which is the
+    /// only format for which the version is sent to servers for revalidation.
+    #[must_use]
+    pub fn is_current(&self) -> bool {
+        self.cache_version == CURRENT_CACHE_VERSION && self.index_format_version == Some(CURRENT_INDEX_FORMAT_VERSION)
+    }
+}
+
+/// A problem with an entry in the local cache of an index, found by [`SparseIndex::verify_cache()`](crate::SparseIndex::verify_cache).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheProblem {
+    /// The path to the cache file.
+    pub path: PathBuf,
+    /// What is wrong with it.
+    pub kind: CacheProblemKind,
+}
+
+/// The kinds of problems cache entries can have, see [`CacheProblem`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheProblemKind {
+    /// The file couldn't be read.
+    Unreadable {
+        /// The IO error that occurred.
+        error: String,
+    },
+    /// The file ends prematurely, for instance as it wasn't written completely.
+    Truncated,
+    /// The format of the entry isn't supported by this crate, nor by current versions of cargo.
+    UnsupportedCacheVersion {
+        /// The version of the format of the entry.
+        version: u8,
+    },
+    /// The entry was written for a different version of the index format.
+    UnsupportedIndexFormatVersion {
+        /// The version of the index format of the entry.
+        version: Option<u32>,
+    },
+    /// The index line of a version of the crate couldn't be parsed.
+    InvalidJson {
+        /// The version of the crate the line is for, as recorded in the cache entry.
+        version: String,
+        /// The line itself.
+        line: String,
+        /// The error that occurred when parsing it.
+        error: String,
+    },
+    /// A version of a crate was found in the cache file of a different crate.
+    NameMismatch {
+        /// The name of the crate as derived from the path of the cache file, which is always lowercase.
+        expected: String,
+        /// The name of the crate in the index line.
+        found: String,
+    },
+}
+
+/// The outcome of verifying the local cache of an index with [`SparseIndex::verify_cache()`](crate::SparseIndex::verify_cache).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// The amount of cache files that were checked.
+    pub checked: usize,
+    /// All problems that were found, with at most one per file.
+    pub problems: Vec<CacheProblem>,
+    /// `true` if the files with problems were deleted.
+    pub repaired: bool,
+}
+
+impl VerifyReport {
+    /// Returns `true` if no problems were found.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Check all entries in the cache of the index at `index_path`, and delete the broken ones if `repair` is `true`.
+pub(crate) fn verify(index_path: &Path, repair: bool) -> Result<VerifyReport, crate::Error> {
+    use crate::lock::{LockMode, PackageCacheLock};
+
+    let _lock = if repair {
+        PackageCacheLock::for_index(index_path, LockMode::MutateExclusive)?
+    } else {
+        None
+    };
+    let cache_dir = index_path.join(CACHE_DIR);
+    let mut report = VerifyReport {
+        repaired: repair,
+        ..Default::default()
+    };
+    for path in Walk::new(&cache_dir) {
+        report.checked += 1;
+        let kind = match std::fs::read(&path) {
+            Ok(bytes) => verify_entry(&bytes, path.strip_prefix(&cache_dir).unwrap_or(&path)),
+            Err(err) => Some(CacheProblemKind::Unreadable { error: err.to_string() }),
+        };
+        let Some(kind) = kind else { continue };
+        if repair {
+            match std::fs::remove_file(&path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        report.problems.push(CacheProblem { path, kind });
+    }
+    Ok(report)
+}
+
+/// Return a problem of the cache entry `bytes` if it has any, which is stored at `rel_path` relative to the cache directory.
+fn verify_entry(bytes: &[u8], rel_path: &Path) -> Option<CacheProblemKind> {
+    let header = match Header::parse(bytes) {
+        Ok(header) => header,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Some(CacheProblemKind::Truncated),
+        Err(_) => return Some(CacheProblemKind::UnsupportedCacheVersion { version: bytes[0] }),
+    };
+    match header.cache_version {
+        CURRENT_CACHE_VERSION if header.index_format_version != Some(CURRENT_INDEX_FORMAT_VERSION) => {
+            return Some(CacheProblemKind::UnsupportedIndexFormatVersion {
+                version: header.index_format_version,
+            })
+        }
+        CURRENT_CACHE_VERSION | 1 => {}
+        version => return Some(CacheProblemKind::UnsupportedCacheVersion { version }),
+    }
+
+    // Problems with the structure of the file are more telling, so they take precedence
+    let mut name_mismatch = None;
+    let mut entries = crate::split(header.entries, 0);
+    while let Some(version) = entries.next() {
+        let Some(line) = entries.next() else {
+            return Some(CacheProblemKind::Truncated);
+        };
+        let parsed = match serde_json::from_slice::<crate::Version>(line) {
+            Ok(parsed) => parsed,
+            Err(err) if err.is_eof() => return Some(CacheProblemKind::Truncated),
+            Err(err) => {
+                return Some(CacheProblemKind::InvalidJson {
+                    version: String::from_utf8_lossy(version).into_owned(),
+                    line: String::from_utf8_lossy(line).into_owned(),
+                    error: err.to_string(),
+                })
+            }
+        };
+        let expected_path = crate::dirs::crate_name_to_relative_path(parsed.name(), None);
+        if name_mismatch.is_none() && expected_path.as_deref().map(Path::new) != Some(rel_path) {
+            name_mismatch = Some(CacheProblemKind::NameMismatch {
+                expected: rel_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                found: parsed.name().to_owned(),
+            });
+        }
+    }
+    name_mismatch
+}
+
+/// Write `contents` to `path` via a temporary file in the same directory which is then renamed,
+/// so readers never see a partially written file.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(path.file_name().ok_or(io::ErrorKind::InvalidInput)?);
+    tmp_name.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp_path = path.with_file_name(tmp_name);
+    match std::fs::write(&tmp_path, contents).and_then(|()| std::fs::rename(&tmp_path, path)) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let _ = std::fs::remove_file(&tmp_path);
+            Err(err)
+        }
+    }
+}
+
+/// Iterator over the paths of all files below a directory, depth-first.
+///
+/// Directories that can't be read are skipped, as are hidden files and directories, which includes the temporary
+/// files of [`write_atomic()`] that may be renamed by a concurrent writer at any time.
+pub(crate) struct Walk {
+    stack: Vec<ReadDir>,
+}
+
+impl Walk {
+    pub(crate) fn new(root: &Path) -> Self {
+        Self {
+            stack: std::fs::read_dir(root).into_iter().collect(),
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let dir = self.stack.last_mut()?;
+            let Some(entry) = dir.next() else {
+                self.stack.pop();
+                continue;
+            };
+            let Ok(entry) = entry else { continue };
+            if entry.file_name().as_encoded_bytes().starts_with(b".") {
+                continue;
+            }
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => {
+                    if let Ok(dir) = std::fs::read_dir(entry.path()) {
+                        self.stack.push(dir);
+                    }
+                }
+                Ok(kind) if kind.is_file() => return Some(entry.path()),
+                _ => {}
+            }
+        }
+    }
+}
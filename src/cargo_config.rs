@@ -0,0 +1,940 @@
+#![allow(clippy::result_large_err)]
+
+use crate::sparse::AuthToken;
+use crate::Error;
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// The name cargo uses for the crates.io registry.
+pub(crate) const CRATES_IO: &str = "crates-io";
+
+/// The parts of the [cargo configuration](https://doc.rust-lang.org/cargo/reference/config.html) which decide
+/// where cargo gets crates from, merged from all configuration files and the environment exactly like cargo does.
+///
+/// * The `.cargo/config.toml` files of the current directory and all of its parents are read, followed by
+///   `$CARGO_HOME/config.toml`. Legacy `config` files without extension are used instead if they exist.
+/// * Files closer to the current directory take precedence. Tables are merged, arrays are concatenated
+///   and other values are replaced.
+/// * Files named by `include` are merged below the file including them, with later ones taking precedence.
+/// * The credentials file `$CARGO_HOME/credentials.toml`, or the legacy `credentials`, takes precedence over
+///   configuration files.
+/// * `CARGO_*` environment variables like `CARGO_REGISTRIES_<NAME>_INDEX` or `CARGO_NET_OFFLINE`
+///   take precedence over all files.
+///
+/// Relative paths of sources and `http.cainfo` are resolved against the parent of the directory of the file
+/// they are in, which is the directory containing `.cargo`.
+///
+/// ```no_run
+/// let config = crates_index::CargoConfig::discover()?;
+/// println!("cargo publishes to {}", config.default_registry_name());
+/// if let Some(index) = config.registry_index("my-registry") {
+///     println!("my-registry is at {index}");
+/// }
+/// # Ok::<_, crates_index::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct CargoConfig {
+    /// The `[source.<name>]` tables used for [source replacement](https://doc.rust-lang.org/cargo/reference/source-replacement.html).
+    pub source: BTreeMap<String, SourceConfig>,
+    /// The `[registries.<name>]` tables of alternative registries and crates.io, by name.
+    ///
+    /// Use [`CargoConfig::registry()`] to look them up like cargo does.
+    pub registries: BTreeMap<String, RegistryConfig>,
+    /// The `[registry]` table.
+    pub registry: RegistrySettings,
+    /// The `[net]` table.
+    pub net: NetConfig,
+    /// The `[http]` table.
+    pub http: HttpConfig,
+    /// The configuration files that were read, from the lowest to the highest precedence.
+    #[serde(skip)]
+    pub files: Vec<PathBuf>,
+}
+
+/// A `[source.<name>]` table, which replaces a source with another one or defines one to replace others with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct SourceConfig {
+    /// The name of the source that replaces this one.
+    pub replace_with: Option<String>,
+    /// The index URL of a registry source.
+    pub registry: Option<String>,
+    /// The directory of a local registry source.
+    pub local_registry: Option<PathBuf>,
+    /// The directory of a directory source, like the one created by `cargo vendor`.
+    pub directory: Option<PathBuf>,
+    /// The URL of a git repository source.
+    pub git: Option<String>,
+    /// The branch of a git repository source.
+    pub branch: Option<String>,
+    /// The tag of a git repository source.
+    pub tag: Option<String>,
+    /// The revision of a git repository source.
+    pub rev: Option<String>,
+}
+
+/// A `[registries.<name>]` table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct RegistryConfig {
+    /// The index URL of the registry.
+    pub index: Option<String>,
+    /// The protocol used for the index, which cargo only honors for `crates-io`.
+    pub protocol: Option<RegistryProtocol>,
+    /// The token to authenticate with, see [`CargoConfig::registry_token()`].
+    token: Option<AuthToken>,
+}
+
+/// The protocols cargo can use to access crates.io, see [`RegistryConfig::protocol`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistryProtocol {
+    /// The git index at <https://github.com/rust-lang/crates.io-index>.
+    Git,
+    /// The sparse index at <https://index.crates.io>.
+    Sparse,
+}
+
+/// The `[registry]` table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct RegistrySettings {
+    /// The name of the registry cargo publishes to by default.
+    pub default: Option<String>,
+    /// The token to authenticate with crates.io, see [`CargoConfig::registry_token()`].
+    token: Option<AuthToken>,
+}
+
+/// The `[net]` table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct NetConfig {
+    /// If `true`, cargo doesn't access the network, and only uses what is available locally.
+    pub offline: bool,
+    /// How often cargo retries network requests that failed with a temporary error.
+    pub retry: Option<u32>,
+    /// If `true`, cargo fetches git repositories with the `git` executable.
+    pub git_fetch_with_cli: Option<bool>,
+}
+
+/// The `[http]` table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+#[non_exhaustive]
+pub struct HttpConfig {
+    /// The HTTP proxy to use, like `host:port`.
+    pub proxy: Option<String>,
+    /// The timeout of requests in seconds.
+    pub timeout: Option<u64>,
+    /// The file with the certificate authorities to trust.
+    pub cainfo: Option<PathBuf>,
+    /// If `false`, certificates aren't checked for revocation.
+    pub check_revoke: Option<bool>,
+    /// The `User-Agent` header to send.
+    pub user_agent: Option<String>,
+    /// If `false`, HTTP/2 multiplexing isn't used.
+    pub multiplexing: Option<bool>,
+    /// The minimum speed in bytes per second below which requests time out.
+    pub low_speed_limit: Option<u32>,
+    /// If `true`, HTTP requests are logged.
+    pub debug: Option<bool>,
+}
+
+impl CargoConfig {
+    /// Read the configuration cargo uses in the current directory, with the configuration of the cargo home
+    /// in `CARGO_HOME` or its default location.
+    pub fn discover() -> Result<Self, Error> {
+        Self::discover_at(std::env::current_dir()?, home::cargo_home()?)
+    }
+
+    /// Read the configuration cargo uses if it is run in `cwd`, with the cargo home at `cargo_home`.
+    ///
+    /// The environment variables of the process are used as well.
+    pub fn discover_at(cwd: impl AsRef<Path>, cargo_home: impl AsRef<Path>) -> Result<Self, Error> {
+        let env =
+            std::env::vars_os().filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)));
+        Self::load(cwd.as_ref(), cargo_home.as_ref(), env)
+    }
+
+    fn load(cwd: &Path, cargo_home: &Path, env: impl IntoIterator<Item = (String, String)>) -> Result<Self, Error> {
+        let cwd = if cwd.is_absolute() {
+            cwd.to_owned()
+        } else {
+            std::env::current_dir()?.join(cwd)
+        };
+        // From the highest to the lowest precedence
+        let mut paths: Vec<_> = cwd
+            .ancestors()
+            .filter_map(|dir| config_file(&dir.join(".cargo")))
+            .collect();
+        if let Some(home_config) = config_file(cargo_home) {
+            if !paths.iter().any(|path| is_same_file(path, &home_config)) {
+                paths.push(home_config);
+            }
+        }
+
+        let mut merged = Value::Table(Table::new());
+        let mut files = Vec::new();
+        for path in paths.iter().rev() {
+            merge(&mut merged, read_with_includes(path, &mut Vec::new(), &mut files)?);
+        }
+        if let Some(path) = credentials_file(cargo_home) {
+            merge(&mut merged, read(&path)?);
+            files.push(path);
+        }
+        let overrides = env_overrides(&merged, env)?;
+        merge(&mut merged, overrides);
+
+        let mut config: Self = merged.try_into()?;
+        config.files = files;
+        Ok(config)
+    }
+
+    /// The `[registries.<name>]` table of the registry `name`, or `None` if it isn't configured.
+    ///
+    /// Like cargo, a table whose name only differs in its use of `-` and `_` matches as well,
+    /// as both are the same in environment variables.
+    #[must_use]
+    pub fn registry(&self, name: &str) -> Option<&RegistryConfig> {
+        self.registries.get(name).or_else(|| {
+            let key = env_key(name);
+            self.registries
+                .iter()
+                .find_map(|(other, registry)| (env_key(other) == key).then_some(registry))
+        })
+    }
+
+    /// The index URL of the registry `name` from `registries.<name>.index`, or `None` if it isn't configured.
+    #[must_use]
+    pub fn registry_index(&self, name: &str) -> Option<&str> {
+        self.registry(name)?.index.as_deref()
+    }
+
+    /// The token cargo authenticates with at the registry `name`, from `registries.<name>.token`, or `registry.token`
+    /// for crates.io, which is usually set in the credentials file or by `CARGO_REGISTRIES_<NAME>_TOKEN`.
+    ///
+    /// Tokens aren't part of the `Debug` output of the configuration.
+    #[must_use]
+    pub fn registry_token(&self, name: &str) -> Option<&str> {
+        let token = if name == CRATES_IO {
+            self.registry.token.as_ref()
+        } else {
+            self.registry(name)?.token.as_ref()
+        };
+        token.map(|token| token.0.as_str())
+    }
+
+    /// The name of the registry cargo publishes to by default, which is `crates-io` unless `registry.default` is set.
+    #[must_use]
+    pub fn default_registry_name(&self) -> &str {
+        self.registry.default.as_deref().unwrap_or(CRATES_IO)
+    }
+
+    /// The protocol cargo uses for crates.io if it isn't replaced, which is set by `registries.crates-io.protocol`
+    /// and is [`RegistryProtocol::Sparse`] by default since cargo 1.70.
+    #[must_use]
+    pub fn crates_io_protocol(&self) -> RegistryProtocol {
+        self.registry(CRATES_IO)
+            .and_then(|registry| registry.protocol)
+            .unwrap_or(RegistryProtocol::Sparse)
+    }
+
+    /// The source cargo gets the crates of the registry `name` from, following the `replace-with` keys of
+    /// [source replacement](https://doc.rust-lang.org/cargo/reference/source-replacement.html), which may name
+    /// sources or registries.
+    ///
+    /// Returns `None` if the registry isn't replaced and has no `registries.<name>.index`, which is the case for
+    /// crates.io unless it is replaced.
+    /// Cycles of replacements and undefined sources are an [`Error::InvalidCargoConfig`], and sources which aren't
+    /// registries, like git repositories, are an [`Error::UnsupportedSource`].
+    pub fn registry_source(&self, name: &str) -> Result<Option<Source>, Error> {
+        let mut chain = vec![name];
+        while let Some(next) = self
+            .source
+            .get(chain[chain.len() - 1])
+            .and_then(|source| source.replace_with.as_deref())
+        {
+            let is_cycle = chain.contains(&next);
+            chain.push(next);
+            if is_cycle {
+                return Err(invalid(format!(
+                    "the `replace-with` keys of the sources form a cycle: {}",
+                    chain.join(" -> ")
+                )));
+            }
+        }
+        let replacement = chain[chain.len() - 1];
+        if replacement == name {
+            return Ok(self.registry_index(name).map(|index| Source::Registry(index.into())));
+        }
+        match (self.source.get(replacement), self.registry_index(replacement)) {
+            (Some(source), _) => source_location(name, replacement, source).map(Some),
+            (None, Some(index)) => Ok(Some(Source::Registry(index.into()))),
+            (None, None) => Err(invalid(format!(
+                "the source '{replacement}' replacing '{name}' is not defined"
+            ))),
+        }
+    }
+}
+
+/// Where cargo gets the crates of a registry from, see [`CargoConfig::registry_source()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Source {
+    /// A registry with its index at this URL, which uses the sparse protocol if it starts with `sparse+`,
+    /// and git otherwise.
+    Registry(String),
+    /// A [local registry](crate::LocalRegistryIndex) in this directory.
+    LocalRegistry(PathBuf),
+    /// A [directory source](crate::DirectoryIndex) in this directory.
+    Directory(PathBuf),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Registry(url) => write!(f, "the registry at '{url}'"),
+            Source::LocalRegistry(path) => write!(f, "the local registry at '{}'", path.display()),
+            Source::Directory(path) => write!(f, "the directory source at '{}'", path.display()),
+        }
+    }
+}
+
+/// The location of the `source` named `source_name`, which replaces the registry `name`.
+fn source_location(name: &str, source_name: &str, source: &SourceConfig) -> Result<Source, Error> {
+    let mut locations: Vec<_> = [
+        source.registry.clone().map(Source::Registry),
+        source.local_registry.clone().map(Source::LocalRegistry),
+        source.directory.clone().map(Source::Directory),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    match (locations.len(), &source.git) {
+        (1, None) => Ok(locations.remove(0)),
+        (0, Some(url)) => Err(Error::UnsupportedSource {
+            name: name.into(),
+            location: format!("the git repository at '{url}'"),
+        }),
+        (0, None) => Err(invalid(format!("the source '{source_name}' has no location"))),
+        _ => Err(invalid(format!(
+            "the source '{source_name}' has more than one location"
+        ))),
+    }
+}
+
+/// The configuration file in the `.cargo` directory or cargo home `dir`, preferring the legacy `config` file like cargo.
+fn config_file(dir: &Path) -> Option<PathBuf> {
+    ["config", "config.toml"]
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// The credentials file in the cargo home `dir`, preferring the legacy `credentials` file like cargo.
+fn credentials_file(dir: &Path) -> Option<PathBuf> {
+    ["credentials", "credentials.toml"]
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Read the TOML file at `path`.
+fn read(path: &Path) -> Result<Value, Error> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| io::Error::new(e.kind(), format!("{}: `{}`", e, path.display())))?;
+    Ok(Value::Table(toml::from_str(&contents)?))
+}
+
+/// Read the configuration file at `path` merged on top of the files it includes, and add all of them to `files`.
+///
+/// `stack` holds the files including this one, to detect cycles.
+fn read_with_includes(path: &Path, stack: &mut Vec<PathBuf>, files: &mut Vec<PathBuf>) -> Result<Value, Error> {
+    if stack.iter().any(|parent| is_same_file(parent, path)) {
+        return Err(invalid(format!("'{}' includes itself", path.display())));
+    }
+    let mut value = read(path)?;
+    if let Some(root) = path.parent().and_then(Path::parent) {
+        resolve_paths(&mut value, root);
+    }
+    let includes = value.as_table_mut().and_then(|table| table.remove("include"));
+
+    let mut merged = Value::Table(Table::new());
+    stack.push(path.to_owned());
+    for (include, optional) in parse_includes(includes, path)? {
+        let include = path.parent().unwrap_or(Path::new(".")).join(include);
+        if optional && !include.is_file() {
+            continue;
+        }
+        merge(&mut merged, read_with_includes(&include, stack, files)?);
+    }
+    stack.pop();
+    merge(&mut merged, value);
+    files.push(path.to_owned());
+    Ok(merged)
+}
+
+/// The paths of the `include` key of the file at `path` and whether they are optional, which is a path,
+/// or an array of paths and tables with a `path` and `optional` key.
+fn parse_includes(includes: Option<Value>, path: &Path) -> Result<Vec<(String, bool)>, Error> {
+    let invalid_include = || invalid(format!("the `include` key in '{}' is invalid", path.display()));
+    let includes = match includes {
+        None => return Ok(Vec::new()),
+        Some(Value::String(include)) => return Ok(vec![(include, false)]),
+        Some(Value::Array(includes)) => includes,
+        Some(_) => return Err(invalid_include()),
+    };
+    includes
+        .into_iter()
+        .map(|include| match include {
+            Value::String(include) => Ok((include, false)),
+            Value::Table(mut table) => match (table.remove("path"), table.remove("optional")) {
+                (Some(Value::String(include)), None) => Ok((include, false)),
+                (Some(Value::String(include)), Some(Value::Boolean(optional))) => Ok((include, optional)),
+                _ => Err(invalid_include()),
+            },
+            _ => Err(invalid_include()),
+        })
+        .collect()
+}
+
+/// Make the relative paths in `value` absolute, relative to `root`, the parent of the `.cargo` directory.
+fn resolve_paths(value: &mut Value, root: &Path) {
+    let resolve = |value: Option<&mut Value>| {
+        if let Some(Value::String(path)) = value {
+            if Path::new(path).is_relative() {
+                *path = root.join(&*path).to_string_lossy().into_owned();
+            }
+        }
+    };
+    if let Some(sources) = value.get_mut("source").and_then(Value::as_table_mut) {
+        for (_, source) in sources.iter_mut() {
+            resolve(source.get_mut("directory"));
+            resolve(source.get_mut("local-registry"));
+        }
+    }
+    resolve(value.get_mut("http").and_then(|http| http.get_mut("cainfo")));
+}
+
+/// Merge `higher` into `lower`, which has a lower precedence.
+fn merge(lower: &mut Value, higher: Value) {
+    match (lower, higher) {
+        (Value::Table(lower), Value::Table(higher)) => {
+            for (key, higher) in higher {
+                match lower.get_mut(&key) {
+                    Some(lower) => merge(lower, higher),
+                    None => {
+                        lower.insert(key, higher);
+                    }
+                }
+            }
+        }
+        (Value::Array(lower), Value::Array(higher)) => lower.extend(higher),
+        (lower, higher) => *lower = higher,
+    }
+}
+
+/// The type of the value of an environment variable.
+#[derive(Clone, Copy)]
+enum Kind {
+    String,
+    Bool,
+    Integer,
+}
+
+/// The environment variables overriding the configuration, without `CARGO_` prefix, with their table, key and type.
+const ENV_KEYS: &[(&str, &str, &str, Kind)] = &[
+    ("REGISTRY_DEFAULT", "registry", "default", Kind::String),
+    ("REGISTRY_TOKEN", "registry", "token", Kind::String),
+    ("NET_OFFLINE", "net", "offline", Kind::Bool),
+    ("NET_RETRY", "net", "retry", Kind::Integer),
+    ("NET_GIT_FETCH_WITH_CLI", "net", "git-fetch-with-cli", Kind::Bool),
+    ("HTTP_PROXY", "http", "proxy", Kind::String),
+    ("HTTP_TIMEOUT", "http", "timeout", Kind::Integer),
+    ("HTTP_CAINFO", "http", "cainfo", Kind::String),
+    ("HTTP_CHECK_REVOKE", "http", "check-revoke", Kind::Bool),
+    ("HTTP_USER_AGENT", "http", "user-agent", Kind::String),
+    ("HTTP_MULTIPLEXING", "http", "multiplexing", Kind::Bool),
+    ("HTTP_LOW_SPEED_LIMIT", "http", "low-speed-limit", Kind::Integer),
+    ("HTTP_DEBUG", "http", "debug", Kind::Bool),
+];
+
+/// The configuration set by the `CARGO_*` variables in `env`, whose `CARGO_REGISTRIES_<NAME>_*` variables apply to the
+/// registries of the `config` read from files if their name matches.
+fn env_overrides(config: &Value, env: impl IntoIterator<Item = (String, String)>) -> Result<Value, Error> {
+    let registry_names: Vec<_> = config
+        .get("registries")
+        .and_then(Value::as_table)
+        .map(|registries| registries.keys().cloned().collect())
+        .unwrap_or_default();
+
+    let mut overrides = Table::new();
+    for (var, value) in env {
+        let Some(key) = var.strip_prefix("CARGO_") else {
+            continue;
+        };
+        if value.is_empty() {
+            continue;
+        }
+        if let Some(&(_, table, key, kind)) = ENV_KEYS.iter().find(|(name, ..)| *name == key) {
+            let value = parse_env_value(&var, value, kind)?;
+            table_mut(&mut overrides, table).insert(key.into(), value);
+            continue;
+        }
+        let Some(registry) = key.strip_prefix("REGISTRIES_") else {
+            continue;
+        };
+        let Some((registry, key)) = registry
+            .strip_suffix("_INDEX")
+            .map(|registry| (registry, "index"))
+            .or_else(|| {
+                registry
+                    .strip_suffix("_PROTOCOL")
+                    .map(|registry| (registry, "protocol"))
+            })
+            .or_else(|| registry.strip_suffix("_TOKEN").map(|registry| (registry, "token")))
+        else {
+            continue;
+        };
+        let name = registry_names
+            .iter()
+            .find(|name| env_key(name) == registry)
+            .cloned()
+            .unwrap_or_else(|| registry.to_ascii_lowercase().replace('_', "-"));
+        let registries = table_mut(&mut overrides, "registries");
+        table_mut(registries, &name).insert(key.into(), Value::String(value));
+    }
+    Ok(Value::Table(overrides))
+}
+
+fn table_mut<'a>(table: &'a mut Table, key: &str) -> &'a mut Table {
+    table
+        .entry(key)
+        .or_insert_with(|| Value::Table(Table::new()))
+        .as_table_mut()
+        .expect("only tables are inserted")
+}
+
+fn parse_env_value(var: &str, value: String, kind: Kind) -> Result<Value, Error> {
+    match kind {
+        Kind::String => Ok(Value::String(value)),
+        Kind::Bool => match value.as_str() {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ => Err(invalid(format!("`{var}` must be `true` or `false`, not '{value}'"))),
+        },
+        Kind::Integer => value
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| invalid(format!("`{var}` must be an integer, not '{value}'"))),
+    }
+}
+
+fn invalid(reason: String) -> Error {
+    Error::InvalidCargoConfig { reason }
+}
+
+/// The form of `name` in environment variables, like `MY_REGISTRY` for `my-registry`.
+fn env_key(name: &str) -> String {
+    name.to_ascii_uppercase().replace('-', "_")
+}
+
+/// The index URL of the registry `name` in `config` for an index using the sparse protocol if `sparse` is `true`,
+/// or git otherwise, after source replacement, with `crates_io_url` being the URL of crates.io if it isn't replaced.
+///
+/// Sources of other kinds are an [`Error::UnsupportedSource`].
+pub(crate) fn registry_url(
+    config: &CargoConfig,
+    name: &str,
+    sparse: bool,
+    crates_io_url: &str,
+) -> Result<String, Error> {
+    match config.registry_source(name)? {
+        Some(Source::Registry(url)) if url.starts_with("sparse+") == sparse => Ok(url),
+        Some(source) => Err(Error::UnsupportedSource {
+            name: name.into(),
+            location: source.to_string(),
+        }),
+        None if name == CRATES_IO => Ok(crates_io_url.into()),
+        None => Err(Error::UnknownRegistry { name: name.into() }),
+    }
+}
+
+/// The name of the registry cargo publishes to by default, which is `crates-io` unless `registry.default` is set
+/// in the cargo configuration or the `CARGO_REGISTRY_DEFAULT` environment variable.
+///
+/// Use it with [`AnyIndex::from_registry_name()`](crate::AnyIndex::from_registry_name()) and its siblings to open
+/// the index of that registry.
+pub fn default_registry_name() -> Result<String, Error> {
+    Ok(CargoConfig::discover()?.default_registry_name().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(dir: &Path, file_name: &str, contents: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join(file_name), contents).unwrap();
+    }
+
+    fn load(cwd: &Path, cargo_home: &Path, env: &[(&str, &str)]) -> Result<CargoConfig, Error> {
+        let env = env.iter().map(|(key, value)| (key.to_string(), value.to_string()));
+        CargoConfig::load(cwd, cargo_home, env)
+    }
+
+    #[test]
+    fn hierarchical_merge() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let (workspace, cargo_home) = (tmp_dir.path().join("workspace"), tmp_dir.path().join("cargo_home"));
+        let member = workspace.join("member");
+        write_config(
+            &cargo_home,
+            "config.toml",
+            r#"
+[registries.home]
+index = "https://home.example.com/index"
+[registries.shared]
+index = "sparse+https://home.example.com/"
+[net]
+offline = true
+retry = 5
+"#,
+        );
+        write_config(
+            &workspace.join(".cargo"),
+            "config",
+            r#"
+[registries.shared]
+index = "sparse+https://workspace.example.com/"
+[source.vendored-sources]
+directory = "vendor"
+[http]
+cainfo = "certs.pem"
+"#,
+        );
+        write_config(
+            &workspace.join(".cargo"),
+            "config.toml",
+            "[registry]\ndefault = \"ignored\"\n",
+        );
+        write_config(
+            &member.join(".cargo"),
+            "config.toml",
+            r#"
+[registries.shared]
+protocol = "sparse"
+[net]
+offline = false
+"#,
+        );
+
+        let config = load(&member, &cargo_home, &[]).unwrap();
+        assert_eq!(config.files.len(), 3);
+        assert_eq!(config.files[0], cargo_home.join("config.toml"));
+        assert_eq!(
+            config.files[1],
+            workspace.join(".cargo/config"),
+            "legacy files are preferred"
+        );
+        assert_eq!(config.registry_index("home"), Some("https://home.example.com/index"));
+        assert_eq!(
+            config.registry("shared"),
+            Some(&RegistryConfig {
+                index: Some("sparse+https://workspace.example.com/".into()),
+                protocol: Some(RegistryProtocol::Sparse),
+                token: None,
+            }),
+            "tables are merged"
+        );
+        assert_eq!(config.registry_index("missing"), None);
+        assert!(
+            !config.net.offline,
+            "files closer to the current directory take precedence"
+        );
+        assert_eq!(config.net.retry, Some(5));
+        assert_eq!(config.default_registry_name(), CRATES_IO);
+        assert_eq!(
+            config.source["vendored-sources"].directory.as_deref(),
+            Some(workspace.join("vendor").as_path()),
+            "paths are relative to the directory containing `.cargo`"
+        );
+        assert_eq!(config.http.cainfo, Some(workspace.join("certs.pem")));
+
+        let config = load(&tmp_dir.path().join("elsewhere"), &cargo_home, &[]).unwrap();
+        assert_eq!(config.files, [cargo_home.join("config.toml")]);
+        assert!(config.net.offline);
+    }
+
+    #[test]
+    fn environment_overrides() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let cargo_home = tmp_dir.path().join("cargo_home");
+        write_config(
+            &cargo_home,
+            "config.toml",
+            "[registries.my_registry]\nindex = \"https://example.com/index\"\n",
+        );
+
+        let config = load(
+            tmp_dir.path(),
+            &cargo_home,
+            &[
+                ("CARGO_REGISTRIES_MY_REGISTRY_INDEX", "sparse+https://env.example.com/"),
+                ("CARGO_REGISTRIES_CRATES_IO_PROTOCOL", "git"),
+                ("CARGO_REGISTRIES_OTHER_INDEX", "sparse+https://other.example.com/"),
+                ("CARGO_REGISTRY_DEFAULT", "other"),
+                ("CARGO_NET_OFFLINE", "true"),
+                ("CARGO_HTTP_TIMEOUT", "30"),
+                ("CARGO_HOME", "ignored"),
+                ("HTTP_TIMEOUT", "ignored"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            config.registry_index("my-registry"),
+            Some("sparse+https://env.example.com/"),
+            "the environment overrides files, and `-` and `_` are the same"
+        );
+        assert_eq!(config.registries.len(), 3);
+        assert_eq!(
+            config.registry(CRATES_IO).and_then(|registry| registry.protocol),
+            Some(RegistryProtocol::Git)
+        );
+        assert_eq!(
+            config.registry_index("other"),
+            Some("sparse+https://other.example.com/")
+        );
+        assert_eq!(config.default_registry_name(), "other");
+        assert!(config.net.offline);
+        assert_eq!(config.http.timeout, Some(30));
+
+        let err = load(tmp_dir.path(), &cargo_home, &[("CARGO_NET_OFFLINE", "yes")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The cargo configuration is invalid: `CARGO_NET_OFFLINE` must be `true` or `false`, not 'yes'"
+        );
+        let err = load(
+            tmp_dir.path(),
+            &cargo_home,
+            &[("CARGO_REGISTRIES_CRATES_IO_PROTOCOL", "ftp")],
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::Toml(_)), "{err:?}");
+    }
+
+    #[test]
+    fn tokens() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let cargo_home = tmp_dir.path().join("cargo_home");
+        write_config(
+            &cargo_home,
+            "config.toml",
+            "[registry]\ntoken = 'config'\n[registries.a]\ntoken = 'config'\n[registries.b]\ntoken = 'config'\n",
+        );
+        write_config(&cargo_home, "credentials.toml", "[registries.a]\ntoken = 'ignored'\n");
+        write_config(&cargo_home, "credentials", "[registries.a]\ntoken = 'secret'\n");
+
+        let config = load(tmp_dir.path(), &cargo_home, &[("CARGO_REGISTRIES_B_TOKEN", "env")]).unwrap();
+        assert_eq!(config.registry_token(CRATES_IO), Some("config"));
+        assert_eq!(
+            config.registry_token("a"),
+            Some("secret"),
+            "the legacy credentials file is preferred, and takes precedence over configuration files"
+        );
+        assert_eq!(config.registry_token("b"), Some("env"));
+        assert_eq!(config.registry_token("c"), None);
+        assert!(!format!("{config:?}").contains("secret"), "tokens are redacted");
+        assert_eq!(config.files.last(), Some(&cargo_home.join("credentials")));
+
+        let config = load(tmp_dir.path(), &cargo_home, &[("CARGO_REGISTRY_TOKEN", "env")]).unwrap();
+        assert_eq!(config.registry_token(CRATES_IO), Some("env"));
+    }
+
+    #[test]
+    fn includes() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let (cwd, cargo_home) = (tmp_dir.path().join("project"), tmp_dir.path().join("cargo_home"));
+        let dot_cargo = cwd.join(".cargo");
+        write_config(
+            &dot_cargo,
+            "config.toml",
+            r#"
+include = ["first.toml", { path = "missing.toml", optional = true }, { path = "second.toml" }]
+[registries.a]
+index = "https://config.example.com/a"
+"#,
+        );
+        write_config(
+            &dot_cargo,
+            "first.toml",
+            "[registries.a]\nindex = \"https://first.example.com/a\"\n[registries.b]\nindex = \"https://first.example.com/b\"\n[registries.c]\nindex = \"https://first.example.com/c\"\n",
+        );
+        write_config(
+            &dot_cargo,
+            "second.toml",
+            "include = \"nested/third.toml\"\n[registries.b]\nindex = \"https://second.example.com/b\"\n",
+        );
+        write_config(
+            &dot_cargo.join("nested"),
+            "third.toml",
+            "[source.vendored]\ndirectory = \"vendor\"\n",
+        );
+
+        let config = load(&cwd, &cargo_home, &[]).unwrap();
+        assert_eq!(
+            config.files,
+            [
+                dot_cargo.join("first.toml"),
+                dot_cargo.join("nested/third.toml"),
+                dot_cargo.join("second.toml"),
+                dot_cargo.join("config.toml"),
+            ]
+        );
+        assert_eq!(
+            config.registry_index("a"),
+            Some("https://config.example.com/a"),
+            "the including file takes precedence"
+        );
+        assert_eq!(
+            config.registry_index("b"),
+            Some("https://second.example.com/b"),
+            "later includes take precedence"
+        );
+        assert_eq!(config.registry_index("c"), Some("https://first.example.com/c"));
+        assert_eq!(
+            config.source["vendored"].directory.as_deref(),
+            Some(dot_cargo.join("vendor").as_path()),
+            "paths are relative to the parent of the directory of the file"
+        );
+
+        write_config(
+            &dot_cargo.join("nested"),
+            "third.toml",
+            "include = \"../config.toml\"\n",
+        );
+        let err = load(&cwd, &cargo_home, &[]).unwrap_err();
+        assert!(matches!(err, Error::InvalidCargoConfig { .. }), "{err:?}");
+        write_config(&dot_cargo, "second.toml", "include = \"missing.toml\"\n");
+        let err = load(&cwd, &cargo_home, &[]).unwrap_err();
+        assert!(matches!(err, Error::Io(_)), "{err:?}");
+    }
+
+    #[test]
+    fn source_replacement() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let (cwd, cargo_home) = (tmp_dir.path().join("project"), tmp_dir.path().join("cargo_home"));
+        write_config(
+            &cwd.join(".cargo"),
+            "config.toml",
+            r#"
+[source.crates-io]
+replace-with = "mirror"
+[source.mirror]
+replace-with = "vendored-sources"
+[source.vendored-sources]
+directory = "vendor"
+
+[source.company]
+replace-with = "company-mirror"
+[registries.company]
+index = "https://company.example.com/index"
+[registries.company-mirror]
+index = "sparse+https://mirror.example.com/"
+
+[registries.plain]
+index = "https://plain.example.com/index"
+[source.local]
+replace-with = "local-registry"
+[source.local-registry]
+local-registry = "registry"
+
+[source.git]
+replace-with = "git-repository"
+[source.git-repository]
+git = "https://example.com/repo.git"
+branch = "main"
+[source.cycle]
+replace-with = "cycle-next"
+[source.cycle-next]
+replace-with = "cycle"
+[source.undefined]
+replace-with = "nothing"
+[source.empty]
+replace-with = "no-location"
+[source.no-location]
+[source.ambiguous]
+replace-with = "two-locations"
+[source.two-locations]
+registry = "sparse+https://example.com/"
+directory = "vendor"
+"#,
+        );
+        let config = load(&cwd, &cargo_home, &[]).unwrap();
+
+        assert_eq!(
+            config.registry_source(CRATES_IO).unwrap(),
+            Some(Source::Directory(cwd.join("vendor"))),
+            "replacements are followed"
+        );
+        assert_eq!(
+            config.registry_source("company").unwrap(),
+            Some(Source::Registry("sparse+https://mirror.example.com/".into())),
+            "registries can replace sources"
+        );
+        assert_eq!(
+            config.registry_source("plain").unwrap(),
+            Some(Source::Registry("https://plain.example.com/index".into()))
+        );
+        assert_eq!(
+            config.registry_source("local").unwrap(),
+            Some(Source::LocalRegistry(cwd.join("registry")))
+        );
+        assert_eq!(config.registry_source("unknown").unwrap(), None);
+
+        let err = config.registry_source("git").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The registry 'git' uses the git repository at 'https://example.com/repo.git', which this index type can't open"
+        );
+        let err = config.registry_source("cycle").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The cargo configuration is invalid: the `replace-with` keys of the sources form a cycle: cycle -> cycle-next -> cycle"
+        );
+        for name in ["undefined", "empty", "ambiguous"] {
+            let err = config.registry_source(name).unwrap_err();
+            assert!(matches!(err, Error::InvalidCargoConfig { .. }), "{err:?}");
+        }
+
+        assert_eq!(
+            registry_url(&config, "company", true, "unused").unwrap(),
+            "sparse+https://mirror.example.com/"
+        );
+        let err = registry_url(&config, "company", false, "unused").unwrap_err();
+        assert!(matches!(err, Error::UnsupportedSource { .. }), "{err:?}");
+        let err = registry_url(&config, CRATES_IO, true, "unused").unwrap_err();
+        assert!(matches!(err, Error::UnsupportedSource { .. }), "{err:?}");
+
+        let config = load(&tmp_dir.path().join("elsewhere"), &cargo_home, &[]).unwrap();
+        assert_eq!(
+            registry_url(&config, CRATES_IO, true, "sparse+https://index.crates.io/").unwrap(),
+            "sparse+https://index.crates.io/"
+        );
+        let err = registry_url(&config, "unknown", true, "unused").unwrap_err();
+        assert!(matches!(err, Error::UnknownRegistry { .. }), "{err:?}");
+    }
+}
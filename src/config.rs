@@ -1,26 +1,65 @@
 use crate::dirs::crate_prefix;
-use serde_derive::Deserialize;
+use crate::Version;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /// Global configuration of an index, reflecting the [contents of config.json](https://doc.rust-lang.org/cargo/reference/registries.html#index-format).
-#[derive(Clone, Debug, Deserialize)]
+///
+/// More fields may be added in minor releases, so use [`IndexConfig::new()`] or deserialize it to create one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct IndexConfig {
     /// Pattern for creating download URLs. Use [`IndexConfig::download_url`] instead.
     pub dl: String,
     /// Base URL for publishing, etc.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub api: Option<String>,
+    /// If `true`, all requests to the index and for downloads must be authenticated.
+    #[serde(default, rename = "auth-required", skip_serializing_if = "std::ops::Not::not")]
+    pub auth_required: bool,
+    /// All keys not known to this crate, which some registries add for their own use.
+    ///
+    /// They are preserved when serializing the configuration.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
 }
 
+/// All markers which may be replaced in [`IndexConfig::dl`].
+const MARKERS: &[&str] = &["{crate}", "{version}", "{prefix}", "{lowerprefix}", "{sha256-checksum}"];
+
 impl IndexConfig {
+    /// Create a configuration which downloads crates from `dl`, see [`IndexConfig::dl`], with all other
+    /// fields at their defaults.
+    #[must_use]
+    pub fn new(dl: impl Into<String>) -> Self {
+        Self {
+            dl: dl.into(),
+            api: None,
+            auth_required: false,
+            extra: BTreeMap::new(),
+        }
+    }
+
     /// Get the URL from where the specified package can be downloaded.
     /// This method assumes the particular version is present in the registry,
     /// and does not verify that it is.
+    ///
+    /// Returns `None` if the download URL needs the checksum of the package,
+    /// use [`IndexConfig::download_url_for`] instead.
     #[must_use]
     pub fn download_url(&self, name: &str, version: &str) -> Option<String> {
-        if !self.dl.contains("{crate}")
-            && !self.dl.contains("{version}")
-            && !self.dl.contains("{prefix}")
-            && !self.dl.contains("{lowerprefix}")
-        {
+        self.make_download_url(name, version, None)
+    }
+
+    /// Get the URL from where the package of `version` can be downloaded,
+    /// filling in its checksum if the registry wants it.
+    #[must_use]
+    pub fn download_url_for(&self, version: &Version) -> Option<String> {
+        self.make_download_url(version.name(), version.version(), Some(version.checksum()))
+    }
+
+    fn make_download_url(&self, name: &str, version: &str, checksum: Option<&[u8; 32]>) -> Option<String> {
+        if !MARKERS.iter().any(|marker| self.dl.contains(marker)) {
             let mut new = String::with_capacity(self.dl.len() + name.len() + version.len() + 10);
             new.push_str(&self.dl);
             new.push('/');
@@ -32,13 +71,16 @@ impl IndexConfig {
         } else {
             let mut prefix = String::with_capacity(5);
             crate_prefix(&mut prefix, name, '/')?;
-            Some(
-                self.dl
-                    .replace("{crate}", name)
-                    .replace("{version}", version)
-                    .replace("{prefix}", &prefix)
-                    .replace("{lowerprefix}", &prefix.to_ascii_lowercase()),
-            )
+            let mut url = self
+                .dl
+                .replace("{crate}", name)
+                .replace("{version}", version)
+                .replace("{prefix}", &prefix)
+                .replace("{lowerprefix}", &prefix.to_ascii_lowercase());
+            if url.contains("{sha256-checksum}") {
+                url = url.replace("{sha256-checksum}", &hex::encode(checksum?));
+            }
+            Some(url)
         }
     }
 }
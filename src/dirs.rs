@@ -79,6 +79,22 @@ pub(crate) fn crate_name_to_relative_path(crate_name: &str, separator: Option<ch
     Some(rel_path)
 }
 
+/// The inverse of [`crate_name_to_relative_path()`] with `/` as separator, returning the lowercase name of the crate
+/// whose index file is at `rel_path` if it is a valid crate name and `rel_path` is exactly where its file is located.
+pub(crate) fn relative_path_to_crate_name(rel_path: &str) -> Option<String> {
+    let name = rel_path.rsplit('/').next()?.to_ascii_lowercase();
+    let is_valid_name = !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !is_valid_name {
+        return None;
+    }
+    crate_name_to_relative_path(&name, Some('/'))
+        .filter(|expected| expected.eq_ignore_ascii_case(rel_path))
+        .map(|_| name)
+}
+
 /// Matches https://github.com/rust-lang/cargo/blob/2928e32734b04925ee51e1ae88bea9a83d2fd451/crates/cargo-util-schemas/src/core/source_kind.rs#L5
 type SourceKind = u64;
 const SOURCE_KIND_REGISTRY: SourceKind = 2;
@@ -1,11 +1,15 @@
 pub use serde_json::Error as SerdeJsonError;
 use std::io;
 use std::path::PathBuf;
+use std::time::Duration;
 pub use toml::de::Error as TomlDeError;
 
 /// The catch-all error for the entire crate.
+///
+/// New variants may be added in minor releases.
 #[derive(Debug, thiserror::Error)]
 #[allow(missing_docs)]
+#[non_exhaustive]
 pub enum Error {
     #[error("\"gix\" crate failed. If problems persist, consider deleting `~/.cargo/registry/index/github.com-1ecc6299db9ec823/`")]
     #[cfg(feature = "git")]
@@ -23,10 +27,117 @@ pub enum Error {
     },
     #[error(transparent)]
     Io(#[from] io::Error),
+    #[error("The request{} was not authorized by the registry", crate_name.as_deref().map(|name| format!(" for crate '{name}'")).unwrap_or_default())]
+    Unauthorized {
+        /// The crate that was requested, or `None` if it was the configuration of the index.
+        crate_name: Option<String>,
+    },
+    #[error("The crate '{crate_name}' was recently reported as missing by the registry")]
+    KnownMissing {
+        /// The name of the crate as it was requested.
+        crate_name: String,
+    },
+    #[error("config.json was not found in the registry")]
+    ConfigNotFound,
+    #[error("The registry '{name}' is not configured, as neither `registries.{name}.index` nor its environment variable are set")]
+    UnknownRegistry {
+        /// The name of the registry.
+        name: String,
+    },
+    #[error("The registry responded with status code {status}{}, which is not supported in the current protocol", crate_name.as_deref().map(|name| format!(" for crate '{name}'")).unwrap_or_default())]
+    HttpStatus {
+        /// The HTTP status code of the response.
+        status: u16,
+        /// The crate that was requested, or `None` if it was the configuration of the index.
+        crate_name: Option<String>,
+        /// How long the server asked to wait before retrying, from the `Retry-After` header.
+        retry_after: Option<Duration>,
+        /// `true` if the status indicates a temporary problem, like rate limiting (429),
+        /// a timeout (408) or a server error (5xx), so the request may succeed if it's repeated.
+        retryable: bool,
+    },
+    #[error("Version {version} of the crate '{crate_name}' is already in the index")]
+    VersionExists {
+        /// The name of the crate.
+        crate_name: String,
+        /// The version that is already in the index.
+        version: String,
+    },
+    #[error("Version {version} of the crate '{crate_name}' is not in the index")]
+    VersionNotFound {
+        /// The name of the crate.
+        crate_name: String,
+        /// The version that was looked for.
+        version: String,
+    },
+    #[error("The index entry is invalid: {reason}")]
+    InvalidEntry {
+        /// What is wrong with the entry.
+        reason: String,
+    },
+    #[error("The package is invalid: {reason}")]
+    InvalidPackage {
+        /// What is wrong with the package.
+        reason: String,
+    },
+    #[error("The registry '{name}' uses {location}, which this index type can't open")]
+    UnsupportedSource {
+        /// The name of the registry.
+        name: String,
+        /// The description of the source the registry uses, possibly after source replacement.
+        location: String,
+    },
+    #[error("The cargo configuration is invalid: {reason}")]
+    InvalidCargoConfig {
+        /// What is wrong with the configuration.
+        reason: String,
+    },
     #[error("If this happens, the registry is seriously corrupted. Consider deleting `~/.cargo/registry/index/`")]
     Json(#[from] SerdeJsonError),
     #[error(transparent)]
     Toml(#[from] TomlDeError),
+    #[error(transparent)]
+    #[cfg(feature = "sparse-ureq")]
+    Ureq(#[from] ureq::Error),
+    #[error(transparent)]
+    #[cfg(feature = "sparse-async")]
+    Reqwest(#[from] reqwest::Error),
+}
+
+impl Error {
+    /// Returns `true` if the error is likely temporary, so repeating the operation that caused it may succeed.
+    ///
+    /// This is the case for network failures and timeouts, as well as responses with a status that indicates
+    /// a temporary problem, see [`Error::HttpStatus`].
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::HttpStatus { retryable, .. } => *retryable,
+            Error::Io(err) => is_retryable_io(err),
+            #[cfg(feature = "sparse-ureq")]
+            Error::Ureq(err) => match err {
+                // The connection was closed before the whole response was received
+                ureq::Error::Io(err) => is_retryable_io(err) || err.kind() == io::ErrorKind::UnexpectedEof,
+                ureq::Error::Timeout(_) | ureq::Error::ConnectionFailed | ureq::Error::HostNotFound => true,
+                _ => false,
+            },
+            #[cfg(feature = "sparse-async")]
+            Error::Reqwest(err) => err.is_timeout() || err.is_connect() || err.is_request() || err.is_body(),
+            _ => false,
+        }
+    }
+}
+
+fn is_retryable_io(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::TimedOut
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+    )
 }
 
 /// Any error produced by `gix` or the `gix-*` family of crates.
@@ -69,6 +180,24 @@ pub enum GixError {
     FetchDuringClone(#[from] gix::clone::fetch::Error),
     #[error(transparent)]
     PeelToKind(#[from] gix::object::peel::to_kind::Error),
+    #[error(transparent)]
+    Open(#[from] gix::open::Error),
+    #[error(transparent)]
+    Init(#[from] gix::init::Error),
+    #[error(transparent)]
+    FindReference(#[from] gix::reference::find::existing::Error),
+    #[error(transparent)]
+    EditReference(#[from] gix::reference::edit::Error),
+    #[error(transparent)]
+    EditTree(#[from] gix::repository::edit_tree::Error),
+    #[error(transparent)]
+    UpdateTree(#[from] gix::objs::tree::editor::Error),
+    #[error(transparent)]
+    WriteTree(#[from] gix::object::tree::editor::write::Error),
+    #[error(transparent)]
+    WriteObject(#[from] gix::object::write::Error),
+    #[error(transparent)]
+    Commit(#[from] gix::commit::Error),
 }
 
 /// Unknown error from [`crate::GitIndex::crates_parallel`]
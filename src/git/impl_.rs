@@ -1,12 +1,15 @@
+use crate::cargo_config;
 use crate::dedupe::DedupeContext;
 use crate::dirs::{
     crate_name_to_relative_path, local_path_and_canonical_url_with_hash_kind, HashKind, DEFAULT_HASHER_KIND,
 };
 use crate::error::GixError;
-use crate::git::{changes, config, URL};
-use crate::{path_max_byte_len, Crate, Error, GitIndex, IndexConfig};
+use crate::git::{changes, URL};
+use crate::lock::{LockMode, PackageCacheLock};
+use crate::{path_max_byte_len, CargoConfig, Crate, Error, GitIndex, Index, IndexConfig};
 use gix::bstr::ByteSlice;
 use gix::config::tree::Key;
+use std::borrow::Cow;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
@@ -65,21 +68,40 @@ impl GitIndex {
     /// This is the recommended way to access Cargo's index.
     /// *Note that this clones a new index if none is present yet.
     ///
-    /// Note this function takes the `CARGO_HOME` environment variable into account
+    /// Note this function takes the `CARGO_HOME` environment variable into account, as well as
+    /// [source replacement](https://doc.rust-lang.org/cargo/reference/source-replacement.html) in the
+    /// [cargo configuration](CargoConfig). Replacements by sources other than git registries are an
+    /// [`Error::UnsupportedSource`], use [`AnyIndex::new_cargo_default()`](crate::AnyIndex::new_cargo_default())
+    /// to open any of them, with the protocol cargo uses.
     ///
     /// ### Concurrency
     ///
-    /// Concurrent invocations may fail if the index needs to be cloned. To prevent that,
-    /// use synchronization mechanisms like mutexes or file locks as needed by the application.
+    /// Cloning is performed while holding the [package cache lock](crate::lock::PackageCacheLock) of cargo,
+    /// so concurrent invocations and cargo wait for each other.
     pub fn new_cargo_default() -> Result<Self, Error> {
-        let url = config::get_crates_io_replacement(None, None)?;
-        Self::from_url(url.as_deref().unwrap_or(URL))
+        Self::from_registry_name(cargo_config::CRATES_IO)
     }
 
     /// Like [`Self::new_cargo_default()`], but read-only without auto-cloning the cargo default git index.
     pub fn try_new_cargo_default() -> Result<Option<Self>, Error> {
-        let url = config::get_crates_io_replacement(None, None)?;
-        Self::try_from_url(url.as_deref().unwrap_or(URL))
+        let url = cargo_config::registry_url(&CargoConfig::discover()?, cargo_config::CRATES_IO, false, URL)?;
+        Self::try_from_url(&url)
+    }
+
+    /// Creates a bare index for the registry `name` as configured for cargo in `registries.<name>.index`
+    /// or `CARGO_REGISTRIES_<NAME>_INDEX`, opening the same location on disk that Cargo uses for it.
+    ///
+    /// `crates-io` is the same as [`Self::new_cargo_default()`]. Like there, source replacement is applied,
+    /// and registries using the sparse protocol or other sources are an [`Error::UnsupportedSource`].
+    /// *Note that this clones a new index if none is present yet.
+    ///
+    /// ### Concurrency
+    ///
+    /// Cloning is performed while holding the [package cache lock](crate::lock::PackageCacheLock) of cargo,
+    /// so concurrent invocations and cargo wait for each other.
+    pub fn from_registry_name(name: &str) -> Result<Self, Error> {
+        let url = cargo_config::registry_url(&CargoConfig::discover()?, name, false, URL)?;
+        Self::from_url(&url)
     }
 
     /// Creates a bare index from a provided URL, opening the same location on
@@ -91,8 +113,8 @@ impl GitIndex {
     ///
     /// ### Concurrency
     ///
-    /// Concurrent invocations may fail if the index needs to be cloned. To prevent that,
-    /// use synchronization mechanisms like mutexes or file locks as needed by the application.
+    /// Cloning is performed while holding the [package cache lock](crate::lock::PackageCacheLock) of cargo,
+    /// so concurrent invocations and cargo wait for each other.
     pub fn from_url(url: &str) -> Result<Self, Error> {
         Self::from_url_with_hash_kind(url, &DEFAULT_HASHER_KIND)
     }
@@ -123,7 +145,9 @@ impl GitIndex {
     ///
     /// ### Concurrency
     ///
-    /// Concurrent invocations may fail if the index needs to be cloned. To prevent that,
+    /// If `path` is in the `registry/index` directory of a cargo home, cloning is performed while holding the
+    /// [package cache lock](crate::lock::PackageCacheLock) of cargo, so concurrent invocations and cargo wait
+    /// for each other. Otherwise, concurrent invocations may fail if the index needs to be cloned. To prevent that,
     /// use synchronization mechanisms like mutexes or file locks as needed by the application.
     pub fn with_path<P: Into<PathBuf>, S: Into<String>>(path: P, url: S) -> Result<Self, Error> {
         Ok(
@@ -258,10 +282,14 @@ impl GitIndex {
             Mode::ReadOnly => repo,
             Mode::CloneUrlToPathIfRepoMissing => Some(match repo {
                 Some(repo) => repo,
-                None => match gix::open_opts(&path, open_with_complete_config).ok() {
-                    None => clone_url(&url, &path)?,
-                    Some(repo) => repo,
-                },
+                None => {
+                    // Cargo or another instance may be cloning right now, and we'd see the result once it's done.
+                    let _lock = PackageCacheLock::for_index(&path, LockMode::DownloadExclusive)?;
+                    match gix::open_opts(&path, open_with_complete_config).ok() {
+                        None => clone_url(&url, &path)?,
+                        Some(repo) => repo,
+                    }
+                }
             }),
         };
 
@@ -305,12 +333,15 @@ impl GitIndex {
     /// Fetches latest from the remote index repository. Note that using this
     /// method will mean no cache entries will be used, if a new commit is fetched
     /// from the repository, as their commit version will no longer match.
+    ///
+    /// Like cargo, the [package cache lock](crate::lock::PackageCacheLock) is held while fetching.
     pub fn update(&mut self) -> Result<(), Error> {
         let mut remote = self
             .repo
             .find_remote("origin")
             .ok()
             .unwrap_or_else(|| self.repo.remote_at(self.url.as_str()).expect("own URL is always valid"));
+        let _lock = PackageCacheLock::for_index(&self.path, LockMode::DownloadExclusive)?;
         fetch_remote(
             &mut remote,
             &["+HEAD:refs/remotes/origin/HEAD", "+master:refs/remotes/origin/master"],
@@ -322,6 +353,14 @@ impl GitIndex {
         Ok(())
     }
 
+    /// Parse every entry in the `.cache` directory that cargo maintains for this index and report those that are broken.
+    ///
+    /// If `repair` is `true`, the broken entries are deleted, which is safe as they are only an acceleration mechanism,
+    /// while holding the [package cache lock](crate::lock::PackageCacheLock) of cargo if the index is located in a cargo home.
+    pub fn verify_cache(&self, repair: bool) -> Result<crate::cache::VerifyReport, Error> {
+        crate::cache::verify(&self.path, repair)
+    }
+
     /// Reads a crate from the index, it will attempt to use a cached entry if
     /// one is available, otherwise it will fallback to reading the crate
     /// directly from the git blob containing the crate information.
@@ -513,6 +552,57 @@ impl GitIndex {
     }
 }
 
+/// Serves the files of the [current commit](GitIndex::commit()), which can be changed with [`GitIndex::set_commit_from_refspec()`].
+#[cfg(feature = "sparse")]
+impl crate::sparse::IndexSource for GitIndex {
+    fn config_file(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.file_at_path("config.json".into())
+    }
+
+    fn crate_file(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        match crate_name_to_relative_path(name, None) {
+            Some(rel_path) => self.file_at_path(rel_path.into()),
+            None => Ok(None),
+        }
+    }
+}
+
+impl GitIndex {
+    fn file_at_path(&self, path: PathBuf) -> Result<Option<Vec<u8>>, Error> {
+        match self.object_at_path(path) {
+            Ok(object) => Ok(Some(object.detach().data)),
+            Err(GixError::PathMissing { .. }) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Reads the [current commit](GitIndex::commit()), which is also what [`Index::last_updated()`] returns for all crates.
+impl Index for GitIndex {
+    fn crate_(&self, name: &str) -> Result<Option<Crate>, Error> {
+        Ok(GitIndex::crate_(self, name))
+    }
+
+    fn index_config(&self) -> Result<Option<IndexConfig>, Error> {
+        match self.file_at_path("config.json".into())? {
+            Some(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn crates(&self) -> Option<Box<dyn Iterator<Item = Crate> + '_>> {
+        Some(Box::new(GitIndex::crates(self)))
+    }
+
+    fn url(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.url)
+    }
+
+    fn last_updated(&self, _name: &str) -> Result<Option<SystemTime>, Error> {
+        Ok(Some(self.time()?))
+    }
+}
+
 fn is_top_level_dir(entry: &gix::object::tree::EntryRef<'_, '_>) -> bool {
     entry.mode().is_tree() && entry.filename().len() <= 2
 }
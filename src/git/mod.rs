@@ -12,12 +12,14 @@ mod changes;
 #[cfg(feature = "git")]
 pub use changes::Changes;
 
-#[cfg(feature = "git")]
-mod config;
-
 #[cfg(feature = "git")]
 mod impl_;
 #[cfg(feature = "git")]
 use impl_::fetch_remote;
 #[cfg(feature = "git")]
 pub use impl_::{Change, Crates};
+
+#[cfg(feature = "git")]
+mod writer;
+#[cfg(feature = "git")]
+pub use writer::IndexWriter;
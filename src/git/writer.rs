@@ -0,0 +1,298 @@
+use crate::dirs::crate_name_to_relative_path;
+use crate::error::GixError;
+use crate::types::is_same_version;
+use crate::{Crate, Error, IndexConfig, Version};
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE: &str = "config.json";
+
+/// The name and email of commits if neither [`IndexWriter::signature()`] nor the git configuration provide one.
+const DEFAULT_SIGNATURE: (&str, &str) = ("crates-index", "crates-index@localhost");
+
+/// Publishes, yanks and unyanks crates in a local git repository of a registry index by committing to
+/// the branch its `HEAD` points to, like crates.io does for its index.
+///
+/// This allows to manage small registries entirely through this crate, while cargo and [`GitIndex`](crate::GitIndex)
+/// clone and fetch the repository as usual.
+///
+/// ```no_run
+/// # #[cfg(feature = "package")]
+/// # {
+/// use crates_index::git::IndexWriter;
+///
+/// let index = IndexWriter::init("registry/index.git")?.signature("registry", "registry@example.com");
+/// index.set_config(&serde_json::from_str(r#"{"dl": "https://example.com/api/v1/crates"}"#)?)?;
+/// let version = crates_index::package::version_from_package_file("target/package/foo-0.1.0.crate")?;
+/// index.publish(&version)?;
+/// index.yank("foo", "0.1.0")?;
+/// # }
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+///
+/// ### Concurrency
+///
+/// Each change is a single commit which is only made if the branch didn't change since it was read, so changes
+/// made concurrently by multiple writers fail instead of being lost, and can be retried.
+pub struct IndexWriter {
+    path: PathBuf,
+    repo: gix::Repository,
+    signature: Option<(String, String)>,
+}
+
+impl IndexWriter {
+    /// Open the existing repository at `path`, which is typically bare.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let repo = gix::open(&path).map_err(GixError::from)?;
+        Ok(Self {
+            path,
+            repo,
+            signature: None,
+        })
+    }
+
+    /// Create an empty bare repository at `path`, whose first commit is made by the first change.
+    ///
+    /// Like in the crates.io index, changes are committed to the `master` branch.
+    pub fn init(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        use gix::refs::transaction::{Change, LogChange, PreviousValue, RefEdit};
+
+        let path = path.into();
+        let repo = gix::init_bare(&path).map_err(GixError::from)?;
+        // `GitIndex` and old versions of cargo expect the `master` branch, which isn't the default of `gix`
+        repo.edit_reference(RefEdit {
+            change: Change::Update {
+                log: LogChange::default(),
+                expected: PreviousValue::Any,
+                new: gix::refs::Target::Symbolic("refs/heads/master".try_into().expect("valid name")),
+            },
+            name: "HEAD".try_into().expect("valid name"),
+            deref: false,
+        })
+        .map_err(GixError::from)?;
+        Ok(Self {
+            path,
+            repo,
+            signature: None,
+        })
+    }
+
+    /// Use `name` and `email` as author and committer of all commits.
+    ///
+    /// By default, the `user.name` and `user.email` of the git configuration are used if they are set.
+    #[must_use]
+    pub fn signature(mut self, name: impl Into<String>, email: impl Into<String>) -> Self {
+        self.signature = Some((name.into(), email.into()));
+        self
+    }
+
+    /// The path of the repository.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// git hash of the most recent commit, or `None` if there is no commit yet.
+    pub fn commit(&self) -> Result<Option<[u8; 20]>, Error> {
+        Ok(self
+            .head_commit()?
+            .map(|id| id.as_bytes().try_into().expect("SHA-1 repository")))
+    }
+
+    /// Get the global configuration of the index, or `None` if there is none yet.
+    pub fn index_config(&self) -> Result<Option<IndexConfig>, Error> {
+        match self.read_file(self.head_commit()?, CONFIG_FILE)? {
+            Some(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Read the crate `name` from the most recent commit, or `None` if it's not in the index.
+    pub fn crate_(&self, name: &str) -> Result<Option<Crate>, Error> {
+        let Some(rel_path) = crate_name_to_relative_path(name, Some('/')) else {
+            return Ok(None);
+        };
+        match self.read_file(self.head_commit()?, &rel_path)? {
+            Some(contents) => Ok(Some(Crate::from_slice(&contents)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Write `config` as `config.json`, and return `true` if it changed and was committed.
+    pub fn set_config(&self, config: &IndexConfig) -> Result<bool, Error> {
+        let mut contents = serde_json::to_vec_pretty(config)?;
+        contents.push(b'\n');
+        let head = self.head_commit()?;
+        if self
+            .read_file(head, CONFIG_FILE)?
+            .is_some_and(|existing| existing == contents)
+        {
+            return Ok(false);
+        }
+        self.commit_file(head, CONFIG_FILE, &contents, "Updating config.json")?;
+        Ok(true)
+    }
+
+    /// Append `version` to the index file of its crate, which is created if this is the first version,
+    /// with a commit like ``Updating crate `foo#0.1.0` ``.
+    ///
+    /// Like crates.io, versions which only differ in their build metadata are considered the same, and publishing
+    /// an existing version is an [`Error::VersionExists`]. All versions of a crate must use the same spelling of its name.
+    pub fn publish(&self, version: &Version) -> Result<(), Error> {
+        let rel_path = crate_name_to_relative_path(version.name(), Some('/')).ok_or_else(|| Error::InvalidPackage {
+            reason: format!("the crate name '{}' is invalid", version.name()),
+        })?;
+        let head = self.head_commit()?;
+        let mut contents = self.read_file(head, &rel_path)?.unwrap_or_default();
+        for existing in index_lines(&contents) {
+            let Ok(existing) = serde_json::from_slice::<Version>(existing) else {
+                continue;
+            };
+            if existing.name() != version.name() {
+                return Err(Error::InvalidPackage {
+                    reason: format!(
+                        "the crate '{}' is named '{}' in the index",
+                        version.name(),
+                        existing.name()
+                    ),
+                });
+            }
+            if is_same_version(existing.version(), version.version()) {
+                return Err(Error::VersionExists {
+                    crate_name: version.name().into(),
+                    version: existing.version().into(),
+                });
+            }
+        }
+        if contents.last().is_some_and(|last| *last != b'\n') {
+            contents.push(b'\n');
+        }
+        contents.extend_from_slice(version.to_index_line().as_bytes());
+        contents.push(b'\n');
+        self.commit_file(
+            head,
+            &rel_path,
+            &contents,
+            &format!("Updating crate `{}#{}`", version.name(), version.version()),
+        )?;
+        Ok(())
+    }
+
+    /// Mark `version` of the crate `name` as yanked with a commit like ``Yanking crate `foo#0.1.0` ``,
+    /// and return `true` if it wasn't yanked before.
+    pub fn yank(&self, name: &str, version: &str) -> Result<bool, Error> {
+        self.set_yanked(name, version, true)
+    }
+
+    /// Mark `version` of the crate `name` as not yanked with a commit like ``Unyanking crate `foo#0.1.0` ``,
+    /// and return `true` if it was yanked before.
+    pub fn unyank(&self, name: &str, version: &str) -> Result<bool, Error> {
+        self.set_yanked(name, version, false)
+    }
+
+    fn set_yanked(&self, name: &str, version: &str, yanked: bool) -> Result<bool, Error> {
+        let not_found = || Error::VersionNotFound {
+            crate_name: name.into(),
+            version: version.into(),
+        };
+        let rel_path = crate_name_to_relative_path(name, Some('/')).ok_or_else(not_found)?;
+        let head = self.head_commit()?;
+        let contents = self.read_file(head, &rel_path)?.ok_or_else(not_found)?;
+
+        let mut found = None;
+        let mut new_contents = Vec::with_capacity(contents.len() + 1);
+        for line in index_lines(&contents) {
+            match serde_json::from_slice::<Version>(line) {
+                Ok(mut existing) if existing.version() == version => {
+                    found = Some(existing.name().to_owned());
+                    if existing.is_yanked() == yanked {
+                        return Ok(false);
+                    }
+                    // Change only the flag to retain fields this crate doesn't know about
+                    let (from, to) = (format!("\"yanked\":{}", !yanked), format!("\"yanked\":{yanked}"));
+                    let line = String::from_utf8_lossy(line);
+                    if line.matches(&from).count() == 1 {
+                        new_contents.extend_from_slice(line.replacen(&from, &to, 1).as_bytes());
+                    } else {
+                        existing.yanked = yanked;
+                        new_contents.extend_from_slice(existing.to_index_line().as_bytes());
+                    }
+                }
+                _ => new_contents.extend_from_slice(line),
+            }
+            new_contents.push(b'\n');
+        }
+        let name = found.ok_or_else(not_found)?;
+        let action = if yanked { "Yanking" } else { "Unyanking" };
+        self.commit_file(
+            head,
+            &rel_path,
+            &new_contents,
+            &format!("{action} crate `{name}#{version}`"),
+        )?;
+        Ok(true)
+    }
+
+    fn head_commit(&self) -> Result<Option<gix::ObjectId>, GixError> {
+        Ok(self.repo.head()?.id().map(gix::Id::detach))
+    }
+
+    /// Read the file at `rel_path` in the commit `head`, which is the one a change is based on.
+    fn read_file(&self, head: Option<gix::ObjectId>, rel_path: &str) -> Result<Option<Vec<u8>>, GixError> {
+        let Some(head) = head else {
+            return Ok(None);
+        };
+        let mut tree = self.repo.find_object(head)?.peel_to_commit()?.tree()?;
+        match tree.peel_to_entry_by_path(rel_path)? {
+            Some(entry) => Ok(Some(entry.object()?.detach().data)),
+            None => Ok(None),
+        }
+    }
+
+    /// Commit `contents` as the file at `rel_path` on top of `parent`, which must be the commit its contents are
+    /// based on, so the branch is only updated if nobody else changed it since.
+    fn commit_file(
+        &self,
+        parent: Option<gix::ObjectId>,
+        rel_path: &str,
+        contents: &[u8],
+        message: &str,
+    ) -> Result<(), GixError> {
+        let tree = match parent {
+            Some(parent) => self.repo.find_object(parent)?.peel_to_commit()?.tree_id()?.detach(),
+            None => gix::ObjectId::empty_tree(self.repo.object_hash()),
+        };
+        let mut editor = self.repo.edit_tree(tree)?;
+        let blob = self.repo.write_blob(contents)?;
+        editor.upsert(rel_path, gix::object::tree::EntryKind::Blob, blob)?;
+        let tree = editor.write()?;
+
+        let signature = self.commit_signature();
+        let mut time = gix::date::parse::TimeBuf::default();
+        let signature = signature.to_ref(&mut time);
+        self.repo
+            .commit_as(signature, signature, "HEAD", message, tree, parent)?;
+        Ok(())
+    }
+
+    fn commit_signature(&self) -> gix::actor::Signature {
+        let (name, email) = match (&self.signature, self.repo.committer()) {
+            (Some((name, email)), _) => (name.as_str().into(), email.as_str().into()),
+            (None, Some(Ok(configured))) => (configured.name.to_owned(), configured.email.to_owned()),
+            (None, _) => (DEFAULT_SIGNATURE.0.into(), DEFAULT_SIGNATURE.1.into()),
+        };
+        gix::actor::Signature {
+            name,
+            email,
+            time: gix::date::Time::now_local_or_utc(),
+        }
+    }
+}
+
+/// The non-empty lines of an index file, without their line endings.
+fn index_lines(contents: &[u8]) -> impl Iterator<Item = &[u8]> {
+    contents
+        .split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty())
+}
@@ -0,0 +1,231 @@
+#![allow(clippy::result_large_err)]
+
+use crate::cargo_config::{self, RegistryProtocol, Source};
+use crate::{AnyIndex, CargoConfig, Crate, DirectoryIndex, Error, IndexConfig, LocalRegistryIndex, SparseIndex};
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Read access that is common to all index backends, like [`GitIndex`](crate::GitIndex),
+/// [`SparseIndex`](crate::SparseIndex), [`LocalRegistryIndex`](crate::LocalRegistryIndex) and
+/// [`DirectoryIndex`](crate::DirectoryIndex), so code analyzing an index can be written once.
+///
+/// All methods read what is available locally and never perform a network request.
+///
+/// As the inherent methods of the backends take precedence over those of this trait, call them like
+/// `Index::crate_(&index, "serde")` when the concrete type is known, or use the [`BoxedIndex`].
+///
+/// ```no_run
+/// use crates_index::{BoxedIndex, Index};
+///
+/// fn newest_version(index: &dyn Index, name: &str) -> Result<Option<String>, crates_index::Error> {
+///     Ok(index.crate_(name)?.map(|krate| krate.highest_version().version().to_owned()))
+/// }
+///
+/// let index: BoxedIndex = crates_index::DirectoryIndex::new("vendor").boxed();
+/// println!("{:?}", newest_version(&index, "serde")?);
+/// # Ok::<_, crates_index::Error>(())
+/// ```
+pub trait Index {
+    /// Read the crate `name`, or `None` if it's not in the index or wasn't fetched yet.
+    ///
+    /// Like cargo, names are matched regardless of their case.
+    fn crate_(&self, name: &str) -> Result<Option<Crate>, Error>;
+
+    /// Get the global configuration of the index, or `None` if it has none or it wasn't fetched yet.
+    fn index_config(&self) -> Result<Option<IndexConfig>, Error>;
+
+    /// Iterate over all crates in the index, skipping those that can't be read,
+    /// or `None` if the backend can't list its crates, like a sparse registry.
+    fn crates(&self) -> Option<Box<dyn Iterator<Item = Crate> + '_>>;
+
+    /// The URL identifying the source of the index, which is a `file://` URL for local backends.
+    fn url(&self) -> Cow<'_, str>;
+
+    /// The time the data of the crate `name` was last updated from its source, or `None` if it's unknown
+    /// or the backend is never updated.
+    ///
+    /// This is the time of the commit being read for git indices, and the time the cache entry of the crate was
+    /// written or revalidated for sparse indices.
+    fn last_updated(&self, name: &str) -> Result<Option<SystemTime>, Error>;
+
+    /// Box this index to use it without knowing its type.
+    #[must_use]
+    fn boxed(self) -> BoxedIndex
+    where
+        Self: Sized + Send + 'static,
+    {
+        Box::new(self)
+    }
+}
+
+/// Any [`Index`], for code that chooses the backend at runtime.
+pub type BoxedIndex = Box<dyn Index + Send>;
+
+/// Implement [`Index`] for a pointer type by forwarding to the index it points to.
+macro_rules! forward_index_impl {
+    ($($ty:ty),*) => {$(
+        impl<T: Index + ?Sized> Index for $ty {
+            fn crate_(&self, name: &str) -> Result<Option<Crate>, Error> {
+                (**self).crate_(name)
+            }
+
+            fn index_config(&self) -> Result<Option<IndexConfig>, Error> {
+                (**self).index_config()
+            }
+
+            fn crates(&self) -> Option<Box<dyn Iterator<Item = Crate> + '_>> {
+                (**self).crates()
+            }
+
+            fn url(&self) -> Cow<'_, str> {
+                (**self).url()
+            }
+
+            fn last_updated(&self, name: &str) -> Result<Option<SystemTime>, Error> {
+                (**self).last_updated(name)
+            }
+        }
+    )*};
+}
+
+forward_index_impl!(&T, Box<T>, Arc<T>);
+
+impl AnyIndex {
+    /// Open the index of crates.io that cargo uses on this machine, with the protocol cargo chooses and at the same
+    /// location on disk.
+    ///
+    /// Like cargo, crates.io may be replaced by another source, see [`CargoConfig::registry_source()`].
+    /// Otherwise, the sparse index is used unless `registries.crates-io.protocol` or
+    /// `CARGO_REGISTRIES_CRATES_IO_PROTOCOL` is `git`, see [`CargoConfig::crates_io_protocol()`].
+    /// The git index needs the `git` feature, and is cloned if it isn't present yet,
+    /// see [`GitIndex::new_cargo_default()`](crate::GitIndex::new_cargo_default()).
+    ///
+    /// Note this function takes the `CARGO_HOME` environment variable into account.
+    pub fn new_cargo_default() -> Result<Self, Error> {
+        Self::from_registry_name(cargo_config::CRATES_IO)
+    }
+
+    /// Open the index of the registry `name` as configured for cargo in `registries.<name>.index` or
+    /// `CARGO_REGISTRIES_<NAME>_INDEX`, using the protocol its URL indicates and the same location on disk
+    /// that Cargo uses for it.
+    ///
+    /// Like cargo, [source replacement](https://doc.rust-lang.org/cargo/reference/source-replacement.html) is
+    /// applied, so the index may also be a local registry or directory source, see
+    /// [`CargoConfig::registry_source()`].
+    /// `crates-io` is the same as [`Self::new_cargo_default()`].
+    /// Registries using the git protocol need the `git` feature, and are cloned if they aren't present yet,
+    /// see [`GitIndex::from_url()`](crate::GitIndex::from_url()).
+    pub fn from_registry_name(name: &str) -> Result<Self, Error> {
+        Self::from_config(&CargoConfig::discover()?, name)
+    }
+
+    fn from_config(config: &CargoConfig, name: &str) -> Result<Self, Error> {
+        match config.registry_source(name)? {
+            Some(source) => Self::from_source(&source),
+            None if name == cargo_config::CRATES_IO => match config.crates_io_protocol() {
+                RegistryProtocol::Sparse => Self::from_url(crate::sparse::URL),
+                RegistryProtocol::Git => Self::from_url(crate::git::URL),
+            },
+            None => Err(Error::UnknownRegistry { name: name.into() }),
+        }
+    }
+
+    /// Open the index of `source`, like one returned by [`CargoConfig::registry_source()`].
+    pub fn from_source(source: &Source) -> Result<Self, Error> {
+        match source {
+            Source::Registry(url) => Self::from_url(url),
+            Source::LocalRegistry(path) => Ok(Self::LocalRegistry(LocalRegistryIndex::new(path))),
+            Source::Directory(path) => Ok(Self::Directory(DirectoryIndex::new(path))),
+        }
+    }
+
+    /// Open the index at `url` with the protocol it indicates, which is sparse for `sparse+` URLs and git otherwise,
+    /// at the same location on disk that Cargo uses for it.
+    pub fn from_url(url: &str) -> Result<Self, Error> {
+        if url.starts_with("sparse+") {
+            return Ok(Self::Sparse(SparseIndex::from_url(url)?));
+        }
+        #[cfg(feature = "git")]
+        {
+            Ok(Self::Git(Box::new(crate::GitIndex::from_url(url)?)))
+        }
+        #[cfg(not(feature = "git"))]
+        Err(Error::Url(format!(
+            "the registry at '{url}' uses the git protocol, which needs the \"git\" feature"
+        )))
+    }
+
+    /// The backend of this index, to use it without knowing its type.
+    fn as_index(&self) -> &dyn Index {
+        match self {
+            #[cfg(feature = "git")]
+            Self::Git(index) => &**index,
+            Self::Sparse(index) => index,
+            Self::LocalRegistry(index) => index,
+            Self::Directory(index) => index,
+        }
+    }
+}
+
+impl Index for AnyIndex {
+    fn crate_(&self, name: &str) -> Result<Option<Crate>, Error> {
+        self.as_index().crate_(name)
+    }
+
+    fn index_config(&self) -> Result<Option<IndexConfig>, Error> {
+        self.as_index().index_config()
+    }
+
+    fn crates(&self) -> Option<Box<dyn Iterator<Item = Crate> + '_>> {
+        self.as_index().crates()
+    }
+
+    fn url(&self) -> Cow<'_, str> {
+        self.as_index().url()
+    }
+
+    fn last_updated(&self, name: &str) -> Result<Option<SystemTime>, Error> {
+        self.as_index().last_updated(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crates_io_like_cargo() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config = CargoConfig::default();
+        let index = AnyIndex::from_config(&config, cargo_config::CRATES_IO).unwrap();
+        assert!(
+            matches!(&index, AnyIndex::Sparse(_)),
+            "cargo uses the sparse protocol by default"
+        );
+        assert_eq!(index.url(), crate::sparse::URL);
+
+        let mut config = CargoConfig::default();
+        config.source.insert(
+            cargo_config::CRATES_IO.into(),
+            toml::from_str("replace-with = 'vendored-sources'").unwrap(),
+        );
+        config.source.insert(
+            "vendored-sources".into(),
+            toml::from_str(&format!("directory = {:?}", tmp_dir.path())).unwrap(),
+        );
+        config.registries.insert(
+            cargo_config::CRATES_IO.into(),
+            toml::from_str("protocol = 'git'").unwrap(),
+        );
+        assert_eq!(config.crates_io_protocol(), RegistryProtocol::Git);
+        let index = AnyIndex::from_config(&config, cargo_config::CRATES_IO).unwrap();
+        assert!(
+            matches!(&index, AnyIndex::Directory(index) if index.path() == tmp_dir.path()),
+            "replacements take precedence over the protocol"
+        );
+
+        let err = AnyIndex::from_config(&config, "unknown").err().unwrap();
+        assert!(matches!(err, Error::UnknownRegistry { .. }), "{err:?}");
+    }
+}
@@ -120,7 +120,8 @@ use std::path::{Path, PathBuf};
 /// Please note that concurrent calls to [`GitIndex::new_cargo_default()`] (and related) will automatically block
 /// and wait for each other, so only one instance will try to clone the index while the others will wait for completion.
 ///
-/// This, however, only protects from itself and `cargo` cloning the index at the same time might interfere.
+/// This is achieved by holding the same [lock](lock::PackageCacheLock) as `cargo` while cloning or fetching, so
+/// `cargo` is waited for as well.
 #[cfg(feature = "git")]
 pub struct GitIndex {
     path: std::path::PathBuf,
@@ -133,15 +134,28 @@ pub struct GitIndex {
 /// The Git based index implementation
 pub mod git;
 
+/// Metadata of the local cache entries that are shared with Cargo
+pub mod cache;
+
 mod config;
 pub use config::IndexConfig;
 
+/// The configuration of cargo, read and merged like cargo does
+pub mod cargo_config;
+pub use cargo_config::{default_registry_name, CargoConfig};
+
 mod dedupe;
 mod dirs;
 pub use dirs::{local_path_and_canonical_url, local_path_and_canonical_url_with_hash_kind, HashKind};
 
 /// Re-exports in case you want to inspect specific error details
 pub mod error;
+
+mod index;
+pub use index::{BoxedIndex, Index};
+
+/// Locking of the package cache of cargo, to safely modify indices while cargo may be running
+pub mod lock;
 #[doc(hidden)]
 #[cfg(feature = "parallel")]
 pub use error::CratesIterError;
@@ -150,16 +164,38 @@ pub use error::Error;
 
 /// Wrapper around managing a sparse HTTP index, re-using Cargo's local disk caches.
 ///
-/// Currently it only uses local Cargo cache, and does not access the network in any way.
+/// By default it only uses local Cargo cache, and does not access the network in any way.
 /// For examples of how to update the local cache,
 /// see [`examples/sparse_http_reqwest.rs`][reqwest] and [`examples/sparse_http_ureq.rs`][ureq].
 ///
+/// With the `sparse-ureq` feature enabled, [`SparseIndex::fetch_crate()`] and [`SparseIndex::fetch_config()`]
+/// perform the requests and update the local cache directly, as do [`SparseIndex::fetch_crates_async()`] and its
+/// siblings with the `sparse-async` feature.
+///
 /// [reqwest]: https://github.com/frewsxcv/rust-crates-index/blob/HEAD/examples/sparse_http_reqwest.rs
 /// [ureq]: https://github.com/frewsxcv/rust-crates-index/blob/HEAD/examples/sparse_http_ureq.rs
 #[derive(Debug)]
 pub struct SparseIndex {
     path: PathBuf,
     url: String,
+    /// How long crates reported as missing by the registry are remembered, if at all.
+    negative_cache_ttl: Option<std::time::Duration>,
+    /// The token to send to registries which require authentication.
+    #[cfg(feature = "sparse")]
+    token: Option<sparse::AuthToken>,
+    /// The configuration on disk once it was read, shared with copies made for blocking work.
+    #[cfg(feature = "sparse")]
+    config: std::sync::Arc<std::sync::Mutex<Option<IndexConfig>>>,
+
+    /// Decides if failed requests of the fetch methods are retried.
+    #[cfg(any(feature = "sparse-ureq", feature = "sparse-async"))]
+    retry_policy: sparse::RetryPolicy,
+    /// The agent used by the blocking fetch methods, created on first use so connections are reused.
+    #[cfg(feature = "sparse-ureq")]
+    agent: std::sync::OnceLock<ureq::Agent>,
+    /// The client used by the async fetch methods, created on first use so connections are reused.
+    #[cfg(feature = "sparse-async")]
+    client: std::sync::OnceLock<reqwest::Client>,
 }
 
 /// The sparse index implementation.
@@ -168,11 +204,81 @@ pub mod sparse;
 #[cfg(feature = "sparse")]
 pub use http;
 
+/// Read-only access to a cargo [local registry](https://doc.rust-lang.org/cargo/reference/source-replacement.html#local-registry-sources),
+/// a directory with an `index` directory laid out like the crates.io index, and the `.crate` files of all versions.
+///
+/// This is what tools like `cargo local-registry` create for air-gapped builds.
+///
+/// ```no_run
+/// let index = crates_index::LocalRegistryIndex::new("vendor/registry");
+/// if let Some(krate) = index.crate_("serde")? {
+///     println!("{}", index.crate_file_path(krate.highest_version()).display());
+/// }
+/// # Ok::<_, crates_index::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct LocalRegistryIndex {
+    path: PathBuf,
+}
+
+/// Read-only access to a cargo [directory source](https://doc.rust-lang.org/cargo/reference/source-replacement.html#directory-sources),
+/// like the `vendor` directory created by `cargo vendor`, with one directory per crate version which contains its
+/// normalized `Cargo.toml` and a `.cargo-checksum.json` file.
+///
+/// As there is no index, the versions are synthesized from the manifests, and contain the same information as
+/// the index entries crates.io creates for them.
+///
+/// ```no_run
+/// let index = crates_index::DirectoryIndex::new("vendor");
+/// for krate in index.crates() {
+///     println!("{} {}", krate.name(), krate.highest_version().version());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DirectoryIndex {
+    path: PathBuf,
+}
+
+/// The index of a registry using any of the protocols and sources cargo supports, for code that opens registries
+/// like cargo does.
+///
+/// Use it through the [`Index`] trait, or match on it to update the index with the methods of its backend.
+///
+/// ```no_run
+/// use crates_index::{AnyIndex, Index};
+///
+/// let index = AnyIndex::from_registry_name(&crates_index::default_registry_name()?)?;
+/// if let Some(krate) = index.crate_("serde")? {
+///     println!("{} is at v{} in {}", krate.name(), krate.highest_version().version(), index.url());
+/// }
+/// # Ok::<_, crates_index::Error>(())
+/// ```
+#[non_exhaustive]
+pub enum AnyIndex {
+    /// A registry using the git protocol.
+    #[cfg(feature = "git")]
+    Git(Box<GitIndex>),
+    /// A registry using the sparse protocol.
+    Sparse(SparseIndex),
+    /// A local registry replacing a registry.
+    LocalRegistry(LocalRegistryIndex),
+    /// A directory source replacing a registry, like vendored crates.
+    Directory(DirectoryIndex),
+}
+
+mod local;
+
+mod manifest;
+
+/// Creating index entries from `.crate` files, as registries do when crates are published.
+#[cfg(feature = "package")]
+pub mod package;
+
 mod names;
 pub use names::Names;
 
 mod types;
-pub use types::{Crate, Dependency, DependencyKind, Version};
+pub use types::{Crate, Dependency, DependencyBuilder, DependencyKind, Version, VersionBuilder};
 
 pub(crate) fn split(haystack: &[u8], needle: u8) -> impl Iterator<Item = &[u8]> + '_ {
     struct Split<'a> {
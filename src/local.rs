@@ -0,0 +1,220 @@
+#![allow(clippy::result_large_err)]
+
+use crate::dirs::{crate_name_to_relative_path, relative_path_to_crate_name};
+use crate::manifest::{invalid, Manifest};
+use crate::{cache, Crate, DirectoryIndex, Error, Index, IndexConfig, LocalRegistryIndex, Version};
+use serde_derive::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+impl LocalRegistryIndex {
+    /// Read the local registry in the directory at `path`, which contains the `index` directory.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The directory of the local registry.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the crate `name` from the index, or returns `None` if it's not in the registry.
+    pub fn crate_(&self, name: &str) -> Result<Option<Crate>, Error> {
+        let Some(rel_path) = crate_name_to_relative_path(name, None) else {
+            return Ok(None);
+        };
+        match std::fs::read(self.path.join("index").join(rel_path)) {
+            Ok(contents) => Ok(Some(Crate::from_slice(&contents)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Iterate over all crates in the index.
+    ///
+    /// Skips crates that can not be parsed.
+    pub fn crates(&self) -> impl Iterator<Item = Crate> + '_ {
+        let index_path = self.path.join("index");
+        cache::Walk::new(&index_path).filter_map(move |path| {
+            let rel_path = path.strip_prefix(&index_path).ok()?.to_str()?;
+            relative_path_to_crate_name(&rel_path.replace(std::path::MAIN_SEPARATOR, "/"))?;
+            Crate::from_slice(&std::fs::read(&path).ok()?).ok()
+        })
+    }
+
+    /// The path of the `.crate` file of `version`, which exists if the registry is complete.
+    #[must_use]
+    pub fn crate_file_path(&self, version: &Version) -> PathBuf {
+        self.path
+            .join(format!("{}-{}.crate", version.name(), version.version()))
+    }
+}
+
+impl DirectoryIndex {
+    /// Read the crates in the directory at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The directory containing the crates.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Synthesizes the crate `name` from all of its versions in the directory, or returns `None` if there are none.
+    ///
+    /// Like `cargo vendor`, versions are expected in directories named like the crate, optionally followed by
+    /// a dash and the version, like `serde` or `serde-1.0.100`.
+    /// As the order in which the versions were published isn't known, they are ordered by their version number.
+    pub fn crate_(&self, name: &str) -> Result<Option<Crate>, Error> {
+        let mut versions = Vec::new();
+        for entry in std::fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let Some(dir_name) = entry.file_name().to_str().map(str::to_ascii_lowercase) else {
+                continue;
+            };
+            let is_candidate = match dir_name.strip_prefix(&name.to_ascii_lowercase()) {
+                Some("") => true,
+                Some(rest) => rest
+                    .strip_prefix('-')
+                    .is_some_and(|version| semver::Version::parse(version).is_ok()),
+                None => false,
+            };
+            if !is_candidate {
+                continue;
+            }
+            if let Some(version) = read_package(&entry.path())? {
+                if version.name().eq_ignore_ascii_case(name) {
+                    versions.push(version);
+                }
+            }
+        }
+        if versions.is_empty() {
+            return Ok(None);
+        }
+        sort_by_version(&mut versions);
+        Crate::from_versions(versions).map(Some)
+    }
+
+    /// Synthesize all crates in the directory, ordered by name.
+    ///
+    /// Skips versions whose manifest or checksum file can not be read.
+    pub fn crates(&self) -> impl Iterator<Item = Crate> {
+        let mut crates = BTreeMap::<_, Vec<_>>::new();
+        for entry in std::fs::read_dir(&self.path).into_iter().flatten().flatten() {
+            if let Ok(Some(version)) = read_package(&entry.path()) {
+                crates
+                    .entry(version.name().to_ascii_lowercase())
+                    .or_default()
+                    .push(version);
+            }
+        }
+        crates.into_values().filter_map(|mut versions| {
+            sort_by_version(&mut versions);
+            Crate::from_versions(versions).ok()
+        })
+    }
+}
+
+impl Index for LocalRegistryIndex {
+    fn crate_(&self, name: &str) -> Result<Option<Crate>, Error> {
+        LocalRegistryIndex::crate_(self, name)
+    }
+
+    /// Local registries usually have no configuration, as the `.crate` files are next to the index.
+    fn index_config(&self) -> Result<Option<IndexConfig>, Error> {
+        match std::fs::read(self.path.join("index").join("config.json")) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn crates(&self) -> Option<Box<dyn Iterator<Item = Crate> + '_>> {
+        Some(Box::new(LocalRegistryIndex::crates(self)))
+    }
+
+    fn url(&self) -> Cow<'_, str> {
+        Cow::Owned(file_url(&self.path))
+    }
+
+    fn last_updated(&self, _name: &str) -> Result<Option<SystemTime>, Error> {
+        Ok(None)
+    }
+}
+
+impl Index for DirectoryIndex {
+    fn crate_(&self, name: &str) -> Result<Option<Crate>, Error> {
+        DirectoryIndex::crate_(self, name)
+    }
+
+    fn index_config(&self) -> Result<Option<IndexConfig>, Error> {
+        Ok(None)
+    }
+
+    fn crates(&self) -> Option<Box<dyn Iterator<Item = Crate> + '_>> {
+        Some(Box::new(DirectoryIndex::crates(self)))
+    }
+
+    fn url(&self) -> Cow<'_, str> {
+        Cow::Owned(file_url(&self.path))
+    }
+
+    fn last_updated(&self, _name: &str) -> Result<Option<SystemTime>, Error> {
+        Ok(None)
+    }
+}
+
+/// The `file://` URL of `path`, which is made absolute if possible, like cargo identifies local sources.
+fn file_url(path: &Path) -> String {
+    let path = std::env::current_dir().map_or_else(|_| path.to_owned(), |dir| dir.join(path));
+    let path = path.to_string_lossy().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("file://{path}")
+    } else {
+        format!("file:///{path}")
+    }
+}
+
+/// The contents of `.cargo-checksum.json`, which cargo uses to verify that vendored sources weren't modified.
+#[derive(Deserialize)]
+struct CargoChecksum {
+    /// The checksum of the `.crate` file, or `None` if the crate doesn't come from a registry.
+    package: Option<String>,
+}
+
+/// Synthesize the index entry of the crate version in `dir`, or return `None` if it's not a crate, like cargo does
+/// for hidden directories and those without `Cargo.toml`.
+///
+/// The checksum is all zeros if the crate doesn't come from a registry, like crates vendored from git repositories.
+fn read_package(dir: &Path) -> Result<Option<Version>, Error> {
+    let manifest_path = dir.join("Cargo.toml");
+    let is_hidden = dir
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    if is_hidden || !manifest_path.is_file() {
+        return Ok(None);
+    }
+    let manifest = Manifest::parse(&std::fs::read_to_string(manifest_path)?)?;
+    let checksum: CargoChecksum = serde_json::from_slice(&std::fs::read(dir.join(".cargo-checksum.json"))?)?;
+    let mut cksum = [0; 32];
+    if let Some(package) = checksum.package {
+        hex::decode_to_slice(&package, &mut cksum).map_err(|err| {
+            invalid(format!(
+                "the checksum of {} {} in '{}' is invalid: {err}",
+                manifest.name(),
+                manifest.version(),
+                dir.display()
+            ))
+        })?;
+    }
+    manifest.into_version(cksum, crate::git::URL).map(Some)
+}
+
+fn sort_by_version(versions: &mut [Version]) {
+    versions.sort_by_cached_key(|version| semver::Version::parse(version.version()).ok());
+}
@@ -0,0 +1,211 @@
+#![allow(clippy::result_large_err)]
+
+use crate::Error;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread::ThreadId;
+
+/// The file cargo locks exclusively while downloading, which includes updating indices.
+const DOWNLOAD_LOCK: &str = ".package-cache";
+/// The file cargo locks while reading (shared) or deleting (exclusive) from its caches.
+const MUTATE_LOCK: &str = ".package-cache-mutate";
+
+/// The ways in which the package cache of cargo can be locked, equivalent to cargo's `CacheLockMode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Allow others to read and download, but prevent deleting from the cache, for example by `cargo clean gc`.
+    Shared,
+    /// Prevent others from downloading and updating indices, while still allowing them to read.
+    ///
+    /// This is what cargo holds while it updates an index, and what this crate uses when cloning
+    /// or fetching git indices and writing cache entries. Versions of cargo before 1.74 only know about this lock.
+    DownloadExclusive,
+    /// Like [`LockMode::DownloadExclusive`], but also prevent anyone from reading, which is needed to delete from the cache.
+    MutateExclusive,
+}
+
+/// A lock on the package cache of cargo, as held by cargo itself while updating indices or downloading crates,
+/// which is released when dropped.
+///
+/// File locks only protect against other processes, so within this process, conflicting locks are coordinated
+/// in memory: a thread may acquire locks on the same file any amount of times, while other threads wait
+/// until it released an exclusive lock, just like other processes do.
+/// Note that a [shared](LockMode::Shared) lock that is upgraded by acquiring an exclusive one stays exclusive
+/// until all locks on the same file are released.
+#[derive(Debug)]
+pub struct PackageCacheLock {
+    /// The thread that acquired the lock, which owns it even if it's dropped elsewhere.
+    owner: ThreadId,
+    /// The lock files we hold in the process-wide registry, released in reverse order.
+    held: Vec<PathBuf>,
+}
+
+impl PackageCacheLock {
+    /// Acquire the lock in `mode` on the package cache in `cargo_home`, blocking until all conflicting locks
+    /// of other threads and processes, like cargo, are released.
+    pub fn acquire(cargo_home: &Path, mode: LockMode) -> Result<Self, Error> {
+        let mut lock = Self {
+            owner: std::thread::current().id(),
+            held: Vec::new(),
+        };
+        match mode {
+            LockMode::Shared => lock.add(cargo_home.join(MUTATE_LOCK), false)?,
+            LockMode::DownloadExclusive => lock.add(cargo_home.join(DOWNLOAD_LOCK), true)?,
+            LockMode::MutateExclusive => {
+                lock.add(cargo_home.join(DOWNLOAD_LOCK), true)?;
+                lock.add(cargo_home.join(MUTATE_LOCK), true)?;
+            }
+        }
+        Ok(lock)
+    }
+
+    /// Like [`Self::acquire()`], but for the cargo home directory that contains the index at `index_path`.
+    ///
+    /// Returns `None` if the index isn't located in the `registry/index` directory of a cargo home,
+    /// as there is no cargo to coordinate with then.
+    pub fn for_index(index_path: &Path, mode: LockMode) -> Result<Option<Self>, Error> {
+        cargo_home_of_index(index_path)
+            .map(|cargo_home| Self::acquire(cargo_home, mode))
+            .transpose()
+    }
+
+    /// Lock the file at `path`, waiting for other threads in memory and for other processes with a file lock.
+    ///
+    /// The registry mutex is never held while blocking on a file lock, so other threads can always release
+    /// their locks in the meantime.
+    fn add(&mut self, path: PathBuf, exclusive: bool) -> Result<(), Error> {
+        let (mutex, changed) = registry();
+        let mut locks = mutex.lock().unwrap_or_else(|err| err.into_inner());
+        loop {
+            let Some(held) = locks.get_mut(&path) else {
+                locks.insert(path.clone(), Held::pending());
+                drop(locks);
+                let file = open_and_lock(&path, exclusive);
+                locks = mutex.lock().unwrap_or_else(|err| err.into_inner());
+                let file = match file {
+                    Ok(file) => file,
+                    Err(err) => {
+                        locks.remove(&path);
+                        changed.notify_all();
+                        return Err(err);
+                    }
+                };
+                let held = locks.get_mut(&path).expect("only we remove pending entries");
+                held.file = Some(file);
+                held.exclusive_owner = exclusive.then_some(self.owner);
+                break;
+            };
+            if held.is_pending() {
+                // Another thread is waiting for the file lock, or upgrading it.
+            } else if held.exclusive_owner == Some(self.owner) || (!exclusive && held.exclusive_owner.is_none()) {
+                break;
+            } else if held.exclusive_owner.is_none() && held.holders.keys().all(|thread| *thread == self.owner) {
+                // Only we hold it shared, so upgrade without letting other threads in until we are done.
+                let file = held.file.take().expect("not pending");
+                drop(locks);
+                let res = fs4::FileExt::lock(&file);
+                locks = mutex.lock().unwrap_or_else(|err| err.into_inner());
+                let held = locks.get_mut(&path).expect("we hold it");
+                held.file = Some(file);
+                changed.notify_all();
+                res?;
+                held.exclusive_owner = Some(self.owner);
+                break;
+            }
+            locks = changed.wait(locks).unwrap_or_else(|err| err.into_inner());
+        }
+        *locks
+            .get_mut(&path)
+            .expect("present")
+            .holders
+            .entry(self.owner)
+            .or_default() += 1;
+        changed.notify_all();
+        drop(locks);
+        self.held.push(path);
+        Ok(())
+    }
+}
+
+impl Drop for PackageCacheLock {
+    fn drop(&mut self) {
+        let (mutex, changed) = registry();
+        let mut locks = mutex.lock().unwrap_or_else(|err| err.into_inner());
+        for path in self.held.drain(..).rev() {
+            let Some(held) = locks.get_mut(&path) else { continue };
+            if let Some(count) = held.holders.get_mut(&self.owner) {
+                *count -= 1;
+                if *count == 0 {
+                    held.holders.remove(&self.owner);
+                }
+            }
+            // Pending entries are kept for the thread that is upgrading them.
+            if held.holders.is_empty() && !held.is_pending() {
+                if let Some(file) = locks.remove(&path).and_then(|held| held.file) {
+                    // Closing the file would release the lock as well.
+                    let _ = fs4::FileExt::unlock(&file);
+                }
+            }
+        }
+        changed.notify_all();
+    }
+}
+
+/// A lock file held by this process.
+#[derive(Debug)]
+struct Held {
+    /// The locked file, or `None` while a thread waits for its file lock without holding the registry mutex.
+    file: Option<File>,
+    /// The thread holding the lock exclusively, if any. Others may only acquire it once it's released.
+    exclusive_owner: Option<ThreadId>,
+    /// The amount of [`PackageCacheLock`] instances using it, by the thread owning them.
+    holders: HashMap<ThreadId, usize>,
+}
+
+impl Held {
+    fn pending() -> Self {
+        Held {
+            file: None,
+            exclusive_owner: None,
+            holders: HashMap::new(),
+        }
+    }
+
+    fn is_pending(&self) -> bool {
+        self.file.is_none()
+    }
+}
+
+fn open_and_lock(path: &Path, exclusive: bool) -> Result<File, Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    if exclusive {
+        fs4::FileExt::lock(&file)?;
+    } else {
+        fs4::FileExt::lock_shared(&file)?;
+    }
+    Ok(file)
+}
+
+fn registry() -> &'static (Mutex<HashMap<PathBuf, Held>>, Condvar) {
+    static LOCKS: OnceLock<(Mutex<HashMap<PathBuf, Held>>, Condvar)> = OnceLock::new();
+    LOCKS.get_or_init(Default::default)
+}
+
+/// Return the cargo home of the index at `index_path` if it's in the location cargo uses, `$CARGO_HOME/registry/index/<dir>`.
+pub(crate) fn cargo_home_of_index(index_path: &Path) -> Option<&Path> {
+    let index_dir = index_path.parent()?;
+    let registry_dir = index_dir.parent()?;
+    (index_dir.file_name()? == "index" && registry_dir.file_name()? == "registry")
+        .then(|| registry_dir.parent())
+        .flatten()
+}
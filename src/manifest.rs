@@ -0,0 +1,183 @@
+#![allow(clippy::result_large_err)]
+
+use crate::{Dependency, DependencyKind, Error, Version};
+use serde_derive::Deserialize;
+use smol_str::SmolStr;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+pub(crate) fn invalid(reason: String) -> Error {
+    Error::InvalidPackage { reason }
+}
+
+/// The parts of a normalized manifest that end up in the index.
+#[derive(Deserialize)]
+pub(crate) struct Manifest {
+    #[serde(alias = "project")]
+    package: Package,
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, ManifestDependency>,
+    #[serde(default, rename = "dev-dependencies", alias = "dev_dependencies")]
+    dev_dependencies: BTreeMap<String, ManifestDependency>,
+    #[serde(default, rename = "build-dependencies", alias = "build_dependencies")]
+    build_dependencies: BTreeMap<String, ManifestDependency>,
+    #[serde(default)]
+    target: BTreeMap<String, TargetDependencies>,
+}
+
+impl Manifest {
+    pub(crate) fn parse(manifest: &str) -> Result<Self, Error> {
+        Ok(toml::from_str(manifest)?)
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.package.name
+    }
+
+    pub(crate) fn version(&self) -> &str {
+        &self.package.version
+    }
+
+    /// Create the index entry of the package with the checksum `cksum` of its `.crate` file, as crates.io would,
+    /// with dependencies of registries other than `registry_url` naming their registry.
+    pub(crate) fn into_version(self, cksum: [u8; 32], registry_url: &str) -> Result<Version, Error> {
+        let package = self.package;
+
+        let mut deps = Vec::new();
+        let tables = [
+            (None, DependencyKind::Normal, self.dependencies),
+            (None, DependencyKind::Dev, self.dev_dependencies),
+            (None, DependencyKind::Build, self.build_dependencies),
+        ]
+        .into_iter()
+        .chain(self.target.into_iter().flat_map(|(target, table)| {
+            [
+                (Some(target.clone()), DependencyKind::Normal, table.dependencies),
+                (Some(target.clone()), DependencyKind::Dev, table.dev_dependencies),
+                (Some(target), DependencyKind::Build, table.build_dependencies),
+            ]
+        }));
+        for (target, kind, table) in tables {
+            for (name, dep) in table {
+                deps.push(dep.into_dependency(name, target.as_deref(), kind, registry_url)?);
+            }
+        }
+        // Like crates.io, order by name and kind, keeping the order of the manifest otherwise
+        deps.sort_by_key(|dep| {
+            (
+                dep.name().to_owned(),
+                match dep.kind() {
+                    DependencyKind::Normal => 0,
+                    DependencyKind::Build => 1,
+                    DependencyKind::Dev => 2,
+                },
+            )
+        });
+
+        Ok(Version {
+            name: package.name.into(),
+            vers: package.version.into(),
+            deps: deps.into(),
+            features: Arc::new(self.features.into_iter().collect()),
+            features2: None,
+            links: package.links.map(|links| Box::new(links.into())),
+            rust_version: package.rust_version.map(Into::into),
+            cksum,
+            yanked: false,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    version: String,
+    links: Option<String>,
+    #[serde(rename = "rust-version")]
+    rust_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TargetDependencies {
+    #[serde(default)]
+    dependencies: BTreeMap<String, ManifestDependency>,
+    #[serde(default, rename = "dev-dependencies", alias = "dev_dependencies")]
+    dev_dependencies: BTreeMap<String, ManifestDependency>,
+    #[serde(default, rename = "build-dependencies", alias = "build_dependencies")]
+    build_dependencies: BTreeMap<String, ManifestDependency>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ManifestDependency {
+    Simple(String),
+    Detailed(DetailedDependency),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct DetailedDependency {
+    version: Option<String>,
+    #[serde(default)]
+    features: Vec<String>,
+    #[serde(default)]
+    optional: bool,
+    #[serde(alias = "default_features")]
+    default_features: Option<bool>,
+    package: Option<String>,
+    registry: Option<String>,
+    registry_index: Option<String>,
+}
+
+impl ManifestDependency {
+    fn into_dependency(
+        self,
+        name: String,
+        target: Option<&str>,
+        kind: DependencyKind,
+        registry_url: &str,
+    ) -> Result<Dependency, Error> {
+        let dep = match self {
+            ManifestDependency::Simple(version) => DetailedDependency {
+                version: Some(version),
+                features: Vec::new(),
+                optional: false,
+                default_features: None,
+                package: None,
+                registry: None,
+                registry_index: None,
+            },
+            ManifestDependency::Detailed(dep) => dep,
+        };
+        if let Some(registry) = dep.registry {
+            return Err(invalid(format!(
+                "the dependency '{name}' refers to the registry '{registry}' by name, which is only known to the cargo configuration of its author"
+            )));
+        }
+        let req = match dep.version {
+            Some(version) => semver::VersionReq::parse(&version)
+                .map_err(|err| {
+                    invalid(format!(
+                        "the version requirement of the dependency '{name}' is invalid: {err}"
+                    ))
+                })?
+                .to_string(),
+            None => "*".into(),
+        };
+        let dep_registry = dep.registry_index.unwrap_or_else(|| crate::git::URL.into());
+        let is_same_registry = dep_registry.trim_end_matches('/') == registry_url.trim_end_matches('/');
+        Ok(Dependency {
+            name: name.into(),
+            req: req.into(),
+            features: Box::new(dep.features.into_boxed_slice()),
+            package: dep.package.map(|package| Box::new(package.into())),
+            kind: Some(kind),
+            registry: (!is_same_registry).then(|| SmolStr::from(dep_registry)),
+            target: target.map(|target| Box::new(target.into())),
+            optional: dep.optional,
+            default_features: dep.default_features.unwrap_or(true),
+        })
+    }
+}
@@ -0,0 +1,72 @@
+#![allow(clippy::result_large_err)]
+
+use crate::manifest::{invalid, Manifest};
+use crate::{Error, Version};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+
+/// Create the index entry of the `.crate` file at `path`, see [`version_from_package()`].
+pub fn version_from_package_file(path: impl AsRef<Path>) -> Result<Version, Error> {
+    version_from_package(&std::fs::read(path)?)
+}
+
+/// Create the index entry of the package in `crate_file`, the contents of a `.crate` file as created by `cargo package`,
+/// as crates.io would when it is published there.
+///
+/// The normalized `Cargo.toml` in the package provides the dependencies, features, `links` and `rust-version`,
+/// and the checksum is computed from `crate_file`.
+/// Use [`Version::to_index_line()`] to produce the line that is appended to the index file of the crate.
+///
+/// ```no_run
+/// let version = crates_index::package::version_from_package_file("target/package/foo-0.1.0.crate")?;
+/// println!("{}", version.to_index_line());
+/// # Ok::<_, crates_index::Error>(())
+/// ```
+pub fn version_from_package(crate_file: &[u8]) -> Result<Version, Error> {
+    version_from_package_with_registry(crate_file, crate::git::URL)
+}
+
+/// Like [`version_from_package()`], but for publishing to the registry with the index at `registry_url`.
+///
+/// As cargo does, dependencies from registries other than `registry_url` name their registry, which includes
+/// dependencies from crates.io, while dependencies from `registry_url` itself don't.
+pub fn version_from_package_with_registry(crate_file: &[u8], registry_url: &str) -> Result<Version, Error> {
+    let manifest = read_manifest(crate_file)?;
+    manifest.into_version(checksum(crate_file), registry_url)
+}
+
+/// The SHA-256 checksum of `crate_file`, as stored in the index in [`Version::checksum()`].
+#[must_use]
+pub fn checksum(crate_file: &[u8]) -> [u8; 32] {
+    Sha256::digest(crate_file).into()
+}
+
+/// Find and parse `<name>-<version>/Cargo.toml` in the gzipped tarball `crate_file`.
+fn read_manifest(crate_file: &[u8]) -> Result<Manifest, Error> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(crate_file));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let mut components = path.components();
+        let (Some(dir), Some(file), None) = (components.next(), components.next(), components.next()) else {
+            continue;
+        };
+        if file.as_os_str() != "Cargo.toml" {
+            continue;
+        }
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents)?;
+        let manifest = Manifest::parse(&contents)?;
+        let expected_dir = format!("{}-{}", manifest.name(), manifest.version());
+        if dir.as_os_str() != expected_dir.as_str() {
+            return Err(invalid(format!(
+                "the manifest of {} is in '{}' instead of '{expected_dir}'",
+                manifest.name(),
+                dir.as_os_str().to_string_lossy()
+            )));
+        }
+        return Ok(manifest);
+    }
+    Err(invalid("there is no Cargo.toml in the package".into()))
+}
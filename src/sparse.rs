@@ -1,14 +1,56 @@
+#![allow(clippy::result_large_err)]
+
+use std::borrow::Cow;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use crate::dirs::{
     crate_name_to_relative_path, local_path_and_canonical_url_with_hash_kind, HashKind, DEFAULT_HASHER_KIND,
 };
-use crate::{path_max_byte_len, Crate, Error, IndexConfig, SparseIndex};
+#[cfg(feature = "sparse")]
+use crate::lock::{LockMode, PackageCacheLock};
+use crate::{cache, cargo_config, path_max_byte_len, CargoConfig, Crate, Error, Index, IndexConfig, SparseIndex};
 
 /// The default URL of the crates.io HTTP index, see [`SparseIndex::from_url`] and [`SparseIndex::new_cargo_default`]
 pub const URL: &str = "sparse+https://index.crates.io/";
 
+/// The name of the file holding the configuration of the index, relative to its root.
+const CONFIG_FILE: &str = "config.json";
+/// The file next to [`CONFIG_FILE`] that holds the version of the configuration.
+#[cfg(feature = "sparse")]
+const CONFIG_VERSION_FILE: &str = ".config.json.version";
+
+mod auth;
+pub use auth::cargo_token;
+pub(crate) use auth::AuthToken;
+
+#[cfg(feature = "sparse-async")]
+mod asynchronous;
+#[cfg(feature = "sparse-ureq")]
+mod blocking;
+
+mod gc;
+pub use gc::{GcEntry, GcPolicy, GcReason, GcReport};
+
+mod export;
+pub use export::{ExportReport, Exporter};
+
+#[cfg(feature = "sparse")]
+mod crawler;
+#[cfg(feature = "sparse")]
+pub use crawler::Crawler;
+
+#[cfg(feature = "sparse")]
+mod retry;
+#[cfg(feature = "sparse")]
+pub use retry::RetryPolicy;
+
+#[cfg(feature = "sparse")]
+mod server;
+#[cfg(feature = "sparse")]
+pub use server::{IndexDir, IndexSource, MemoryIndex, SparseServer};
+
 impl SparseIndex {
     /// Creates a view over the sparse HTTP index from a provided URL, opening
     /// the same location on disk that Cargo uses for that registry index's
@@ -26,15 +68,37 @@ impl SparseIndex {
         Self::with_path_and_hash_kind(home::cargo_home()?, url, hash_kind)
     }
 
+    /// Creates a view over the sparse HTTP index of the registry `name` as configured for cargo in
+    /// `registries.<name>.index` or `CARGO_REGISTRIES_<NAME>_INDEX`, opening the same location on disk that
+    /// Cargo uses for it.
+    ///
+    /// `crates-io` is the same as [`Self::new_cargo_default()`]. Like there, source replacement is applied,
+    /// and registries using the git protocol or other sources are an [`Error::UnsupportedSource`].
+    pub fn from_registry_name(name: &str) -> Result<Self, Error> {
+        let config = CargoConfig::discover()?;
+        let url = cargo_config::registry_url(&config, name, true, URL)?;
+        let index = Self::from_url(&url)?;
+        #[cfg(any(feature = "sparse-ureq", feature = "sparse-async"))]
+        let index = Self {
+            retry_policy: RetryPolicy::from_cargo_config(&config),
+            ..index
+        };
+        Ok(index)
+    }
+
     /// Creates an index for the default crates.io registry, using the same
     /// disk location as Cargo itself.
     ///
     /// This is the recommended way to access the crates.io sparse index.
     ///
-    /// Note this function takes the `CARGO_HOME` environment variable into account
+    /// Note this function takes the `CARGO_HOME` environment variable into account, as well as
+    /// [source replacement](https://doc.rust-lang.org/cargo/reference/source-replacement.html) in the
+    /// [cargo configuration](CargoConfig). Replacements by sources other than sparse registries are an
+    /// [`Error::UnsupportedSource`], use [`AnyIndex::new_cargo_default()`](crate::AnyIndex::new_cargo_default())
+    /// to open any of them, with the protocol cargo uses.
     #[inline]
     pub fn new_cargo_default() -> Result<Self, Error> {
-        Self::from_url(URL)
+        Self::from_registry_name(cargo_config::CRATES_IO)
     }
 
     /// Creates a view over the sparse HTTP index from the provided URL, rooted
@@ -69,21 +133,76 @@ impl SparseIndex {
         if !url.ends_with('/') {
             url.push('/');
         }
-        Self { path, url }
+        Self {
+            path,
+            url,
+            negative_cache_ttl: None,
+            #[cfg(feature = "sparse")]
+            token: None,
+            #[cfg(feature = "sparse")]
+            config: Default::default(),
+            #[cfg(any(feature = "sparse-ureq", feature = "sparse-async"))]
+            retry_policy: RetryPolicy::default(),
+            #[cfg(feature = "sparse-ureq")]
+            agent: Default::default(),
+            #[cfg(feature = "sparse-async")]
+            client: Default::default(),
+        }
     }
 
     /// Get the global configuration of the index. There are no guarantees around freshness,
     /// and if the config is not available, no fetch will be performed.
     pub fn index_config(&self) -> Result<IndexConfig, Error> {
-        let path = self.path.join("config.json");
+        let path = self.path.join(CONFIG_FILE);
         let bytes = std::fs::read(path).map_err(Error::Io)?;
 
         serde_json::from_slice(&bytes).map_err(Error::Json)
     }
 
+    /// Like [`Self::index_config()`], but returns `None` if there is no configuration on disk yet,
+    /// which is the case until it was fetched for the first time.
+    pub fn try_index_config(&self) -> Result<Option<IndexConfig>, Error> {
+        match self.index_config() {
+            Ok(config) => Ok(Some(config)),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns `true` if the configuration on disk requires authentication.
+    ///
+    /// It's only read once and then kept until [`Self::parse_config_response()`] writes a new one.
+    #[cfg(feature = "sparse")]
+    fn auth_required(&self) -> bool {
+        let mut config = self.config.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        if config.is_none() {
+            *config = self.index_config().ok();
+        }
+        config.as_ref().is_some_and(|config| config.auth_required)
+    }
+
+    /// Reads the version of the configuration on disk, of the same form as the version of cache entries,
+    /// if the configuration exists and the server provided a validator for it.
+    #[cfg(feature = "sparse")]
+    fn read_config_version(&self) -> Option<String> {
+        if !self.path.join(CONFIG_FILE).is_file() {
+            return None;
+        }
+        std::fs::read_to_string(self.path.join(CONFIG_VERSION_FILE))
+            .ok()
+            .filter(|version| !version.is_empty())
+    }
+
     /// Reads a crate from the local cache of the index. There are no guarantees around freshness,
     /// and if the crate is not known in the cache, no fetch will be performed.
+    ///
+    /// If the crate is [known to be missing](Self::set_negative_cache_ttl()), [`Error::KnownMissing`] is returned.
     pub fn crate_from_cache(&self, name: &str) -> Result<Crate, Error> {
+        if self.is_known_missing(name) {
+            return Err(Error::KnownMissing {
+                crate_name: name.to_owned(),
+            });
+        }
         let cache_path = self
             .cache_path(name)
             .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "bad name"))?;
@@ -93,6 +212,76 @@ impl SparseIndex {
         Ok(Crate::from_cache_slice(&cache_bytes, None)?)
     }
 
+    /// Reads the metadata of the local cache entry of a crate, like the validator that is used to revalidate
+    /// it with the server and the time it was last written or revalidated.
+    ///
+    /// No fetch will be performed, and an [`io::ErrorKind::NotFound`] error is returned if there is no cache entry.
+    pub fn cache_entry_info(&self, name: &str) -> Result<cache::CacheEntryInfo, Error> {
+        let cache_path = self
+            .cache_path(name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "bad name"))?;
+
+        cache::CacheEntryInfo::read(&cache_path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: `{}`", e, cache_path.display())).into())
+    }
+
+    /// Reads a crate from the local cache of the index if its cache entry was written or revalidated
+    /// within `max_age`, see [`Self::cache_entry_info()`].
+    ///
+    /// Returns `None` if there is no cache entry, or if it is older than `max_age` and should be revalidated.
+    /// No fetch will be performed.
+    pub fn crate_from_cache_if_fresh(&self, name: &str, max_age: std::time::Duration) -> Result<Option<Crate>, Error> {
+        if self.is_known_missing(name) {
+            return Ok(None);
+        }
+        match self.cache_entry_info(name) {
+            Ok(info) if info.age() <= max_age => self.crate_from_cache(name).map(Some),
+            Ok(_) => Ok(None),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Parse every entry in the local cache of the index and report those that are broken,
+    /// which would otherwise be treated like missing entries.
+    ///
+    /// If `repair` is `true`, the broken entries are deleted so they are fetched again when needed,
+    /// while holding the [package cache lock](crate::lock::PackageCacheLock) of cargo if the index
+    /// is located in a cargo home.
+    pub fn verify_cache(&self, repair: bool) -> Result<cache::VerifyReport, Error> {
+        cache::verify(&self.path, repair)
+    }
+
+    /// Iterate over all crates in the local cache of the index, along with the version of their cache entry.
+    ///
+    /// This only sees crates that were fetched before, by this crate or by cargo, and no fetch will be performed.
+    /// Entries that can't be read or parsed are skipped.
+    #[must_use]
+    pub fn cached_crates(&self) -> CachedCrates {
+        CachedCrates {
+            paths: cache::Walk::new(&self.path.join(cache::CACHE_DIR)),
+        }
+    }
+
+    /// Like [`Self::cached_crates()`], but reads and parses the cache entries using rayon.
+    ///
+    /// This method is available only if the "parallel" feature is enabled.
+    #[cfg(feature = "parallel")]
+    #[must_use]
+    pub fn cached_crates_parallel(&self) -> impl rayon::iter::ParallelIterator<Item = (Crate, String)> {
+        use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+        let paths: Vec<_> = cache::Walk::new(&self.path.join(cache::CACHE_DIR)).collect();
+        paths.into_par_iter().filter_map(|path| read_cache_entry(&path))
+    }
+
+    /// Get the index directory.
+    #[inline]
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     /// The HTTP url of the index
     #[inline]
     #[must_use]
@@ -114,51 +303,116 @@ impl SparseIndex {
 
     /// Gets the full path to the cache file for the specified crate
     fn cache_path(&self, name: &str) -> Option<PathBuf> {
+        self.entry_path(cache::CACHE_DIR, name)
+    }
+
+    /// Gets the full path to the file marking the specified crate as missing
+    fn missing_path(&self, name: &str) -> Option<PathBuf> {
+        self.entry_path(cache::MISSING_DIR, name)
+    }
+
+    fn entry_path(&self, dir: &str, name: &str) -> Option<PathBuf> {
         let rel_path = crate_name_to_relative_path(name, None)?;
 
         // avoid realloc on each push
-        let mut cache_path = PathBuf::with_capacity(path_max_byte_len(&self.path) + 8 + rel_path.len());
-        cache_path.push(&self.path);
-        cache_path.push(".cache");
-        cache_path.push(rel_path);
+        let mut path = PathBuf::with_capacity(path_max_byte_len(&self.path) + dir.len() + 2 + rel_path.len());
+        path.push(&self.path);
+        path.push(dir);
+        path.push(rel_path);
 
-        Some(cache_path)
+        Some(path)
+    }
+
+    /// Remember crates that the registry reported as missing for `ttl`, or disable it with `None`, which is the default.
+    ///
+    /// While enabled, [`Self::parse_cache_response()`] records the time at which a crate was reported missing
+    /// if it is allowed to write the cache, and until `ttl` passed, [`Self::crate_from_cache()`] and
+    /// [`Self::make_cache_request()`] fail with [`Error::KnownMissing`] for it instead of reading the cache
+    /// or producing a request. This avoids repeated requests when probing names that likely don't exist.
+    pub fn set_negative_cache_ttl(&mut self, ttl: Option<std::time::Duration>) {
+        self.negative_cache_ttl = ttl;
+    }
+
+    /// Returns `true` if the registry reported the crate `name` as missing within the
+    /// [negative cache TTL](Self::set_negative_cache_ttl()).
+    ///
+    /// This is always `false` if the negative cache is disabled.
+    #[must_use]
+    pub fn is_known_missing(&self, name: &str) -> bool {
+        let Some(ttl) = self.negative_cache_ttl else {
+            return false;
+        };
+        let Some(recorded) = self
+            .missing_path(name)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|secs| secs.trim().parse::<u64>().ok())
+        else {
+            return false;
+        };
+        let recorded = std::time::UNIX_EPOCH + std::time::Duration::from_secs(recorded);
+        recorded.elapsed().unwrap_or_default() <= ttl
+    }
+
+    /// Write the negative cache entry for `name`, ignoring errors as the negative cache is only an optimization.
+    #[cfg(feature = "sparse")]
+    fn record_missing(&self, name: &str) {
+        let Some(path) = self.missing_path(name) else {
+            return;
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let Ok(_lock) = self.lock_for_writing() else {
+            return;
+        };
+        if std::fs::create_dir_all(path.parent().unwrap()).is_ok() {
+            let _ = cache::write_atomic(&path, now.as_secs().to_string().as_bytes());
+        }
+    }
+
+    /// Lock the package cache of cargo for writing, if the index is located in a cargo home.
+    #[cfg(feature = "sparse")]
+    fn lock_for_writing(&self) -> Result<Option<PackageCacheLock>, Error> {
+        PackageCacheLock::for_index(&self.path, LockMode::DownloadExclusive)
     }
 
     /// Reads the version of the cache entry for the specified crate, if it exists
+    /// and has the current format.
     ///
     /// The version is of the form `key:value`, where, currently, the key is either
     /// `etag` or `last-modified`
     #[cfg(feature = "sparse")]
     fn read_cache_version(&self, name: &str) -> Option<String> {
-        let cache_path = self.cache_path(name)?;
-        let bytes = std::fs::read(cache_path).ok()?;
-
-        const CURRENT_CACHE_VERSION: u8 = 3;
-        const CURRENT_INDEX_FORMAT_VERSION: u32 = 2;
-
-        let (&first_byte, rest) = bytes.split_first()?;
-
-        if first_byte != CURRENT_CACHE_VERSION {
-            return None;
-        }
-
-        let index_v_bytes = rest.get(..4)?;
-        let index_v = u32::from_le_bytes(index_v_bytes.try_into().unwrap());
-        if index_v != CURRENT_INDEX_FORMAT_VERSION {
-            return None;
-        }
-        let rest = &rest[4..];
+        let info = cache::CacheEntryInfo::read(&self.cache_path(name)?).ok()?;
+        info.is_current().then(|| info.version().to_owned())
+    }
 
-        let version = crate::split(rest, 0)
-            .next()
-            .and_then(|version| std::str::from_utf8(version).ok().map(String::from));
+    /// Set the `token` to send in the `Authorization` header of requests, or remove it with `None`.
+    ///
+    /// It's always sent when requesting the configuration of the index, and with requests for crates if the
+    /// configuration on disk says that the index requires authentication (see [`IndexConfig::auth_required`]).
+    ///
+    /// Use [`cargo_token()`] to obtain the token that cargo would use.
+    #[cfg(feature = "sparse")]
+    pub fn set_auth_token(&mut self, token: Option<String>) {
+        self.token = token.map(AuthToken);
+    }
 
-        version
+    /// Set the `policy` that decides if requests made by the fetch methods are retried, which is
+    /// [`RetryPolicy::default()`] unless set, or [`RetryPolicy::from_cargo_config()`] for indices
+    /// opened with [`Self::from_registry_name()`] or [`Self::new_cargo_default()`].
+    #[cfg(any(feature = "sparse-ureq", feature = "sparse-async"))]
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
     }
 
     #[cfg(feature = "sparse")]
-    fn make_request(&self, url: &str, cache_version: Option<&str>) -> Result<http::request::Builder, Error> {
+    fn make_request(
+        &self,
+        url: &str,
+        cache_version: Option<&str>,
+        token: Option<&AuthToken>,
+    ) -> Result<http::request::Builder, Error> {
         use http::header;
 
         let mut req = http::Request::get(url).version(http::Version::HTTP_2);
@@ -199,6 +453,14 @@ impl SparseIndex {
                     }
                 }
             }
+
+            if let Some(token) = token {
+                let mut value = header::HeaderValue::from_str(&token.0).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "the token is not a valid header value")
+                })?;
+                value.set_sensitive(true);
+                headers.insert(header::AUTHORIZATION, value);
+            }
         }
 
         Ok(req)
@@ -210,11 +472,19 @@ impl SparseIndex {
     /// See [`Self::parse_config_response()`] processing the response from the remote
     /// index.
     ///
+    /// If the configuration was written by [`Self::parse_config_response()`] before, its version is sent along
+    /// so that the server can answer with *Not Modified* if it didn't change.
+    ///
     /// It is highly recommended to assume HTTP/2 when making requests to remote
     /// indices, at least crates.io.
     #[cfg(feature = "sparse")]
     pub fn make_config_request(&self) -> Result<http::request::Builder, Error> {
-        self.make_request(&format!("{}config.json", self.url()), None)
+        // The configuration is where we learn whether authentication is required, so send the token if there is one
+        self.make_request(
+            &format!("{}{CONFIG_FILE}", self.url()),
+            self.read_config_version().as_deref(),
+            self.token.as_ref(),
+        )
     }
 
     /// Creates an HTTP request that can be sent via your HTTP client of choice
@@ -225,19 +495,29 @@ impl SparseIndex {
     ///
     /// It is highly recommended to assume HTTP/2 when making requests to remote
     /// indices, at least crates.io.
+    ///
+    /// If the crate is [known to be missing](Self::set_negative_cache_ttl()), [`Error::KnownMissing`] is returned.
     #[cfg(feature = "sparse")]
     pub fn make_cache_request(&self, name: &str) -> Result<http::request::Builder, Error> {
+        if self.is_known_missing(name) {
+            return Err(Error::KnownMissing {
+                crate_name: name.to_owned(),
+            });
+        }
         self.make_request(
             &self
                 .crate_url(name)
                 .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "crate name is invalid"))?,
             self.read_cache_version(name).as_deref(),
+            self.token.as_ref().filter(|_| self.auth_required()),
         )
     }
 
     /// Process the response to a request created by [`Self::make_config_request()`].
     ///
-    /// If `write_config` is `true`, write the configuration to disk after parsing it.
+    /// If `write_config` is `true`, write the configuration to disk after parsing it, along with
+    /// its version to revalidate it next time. If the server responded with *Not Modified*,
+    /// the configuration on disk is returned.
     /// Note that the write operation may fail, and as opposed to the similar parameter
     /// in [`Self::parse_cache_response()`], write errors will not be ignored.
     ///
@@ -257,25 +537,26 @@ impl SparseIndex {
             StatusCode::OK => {
                 let res = serde_json::from_slice(&body).map_err(Error::Json);
                 if write_config {
-                    let path = self.path.join("config.json");
+                    let path = self.path.join(CONFIG_FILE);
+                    let version_path = self.path.join(CONFIG_VERSION_FILE);
+                    let _lock = self.lock_for_writing()?;
                     std::fs::create_dir_all(path.parent().unwrap())?;
-                    std::fs::write(&path, &body)?;
+                    cache::write_atomic(&path, &body)?;
+                    match response_version(&parts.headers) {
+                        Some(version) => cache::write_atomic(&version_path, version.as_bytes())?,
+                        None => match std::fs::remove_file(version_path) {
+                            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                            _ => {}
+                        },
+                    }
+                    *self.config.lock().unwrap_or_else(std::sync::PoisonError::into_inner) = res.as_ref().ok().cloned();
                 }
                 res
             }
-            StatusCode::UNAUTHORIZED => {
-                Err(io::Error::new(io::ErrorKind::PermissionDenied, "the request was not authorized").into())
-            }
-            StatusCode::NOT_FOUND => {
-                Err(io::Error::new(io::ErrorKind::NotFound, "config.json not found in registry").into())
-            }
-            other => Err(io::Error::new(
-                io::ErrorKind::Unsupported,
-                format!(
-                    "the server responded with status code '{other}', which is not supported in the current protocol"
-                ),
-            )
-            .into()),
+            // The configuration on disk is up to date
+            StatusCode::NOT_MODIFIED => self.index_config(),
+            StatusCode::NOT_FOUND => Err(Error::ConfigNotFound),
+            _ => Err(status_error(&parts, None)),
         }
     }
 
@@ -298,7 +579,7 @@ impl SparseIndex {
         response: http::Response<Vec<u8>>,
         write_cache_entry: bool,
     ) -> Result<Option<Crate>, Error> {
-        use http::{header, StatusCode};
+        use http::StatusCode;
         let (parts, body) = response.into_parts();
 
         match parts.status {
@@ -307,19 +588,11 @@ impl SparseIndex {
                 let krate = Crate::from_slice(&body)?;
 
                 if write_cache_entry {
-                    // The same as cargo, prefer etag over last-modified
-                    let version = if let Some(etag) = parts.headers.get(header::ETAG) {
-                        etag.to_str().ok().map(|etag| format!("{}: {etag}", header::ETAG))
-                    } else if let Some(lm) = parts.headers.get(header::LAST_MODIFIED) {
-                        lm.to_str().ok().map(|lm| format!("{}: {lm}", header::LAST_MODIFIED))
-                    } else {
-                        None
-                    };
-
-                    let version = version.unwrap_or_else(|| "Unknown".to_owned());
+                    let version = response_version(&parts.headers).unwrap_or_else(|| "Unknown".to_owned());
 
                     // This should always succeed, but no need to panic or fail
-                    if let Some(cache_path) = self.cache_path(name) {
+                    let lock = self.lock_for_writing();
+                    if let (Some(cache_path), Ok(_lock)) = (self.cache_path(name), lock) {
                         if std::fs::create_dir_all(cache_path.parent().unwrap()).is_ok() {
                             // It's unfortunate if this fails for some reason, but
                             // not writing the cache entry shouldn't stop the user
@@ -327,30 +600,153 @@ impl SparseIndex {
                             let _ = krate.write_cache_entry(&cache_path, &version);
                         }
                     }
+                    if let Some(missing_path) = self.missing_path(name) {
+                        // The crate exists (again), so it must not be considered missing anymore
+                        let _ = std::fs::remove_file(missing_path);
+                    }
                 }
 
                 Ok(Some(krate))
             }
             // The local cache entry is up to date with the latest entry on the
             // server, we can just return the local one
-            StatusCode::NOT_MODIFIED => self.crate_from_cache(name).map(Option::Some),
-            // The server requires authorization but the user didn't provide it
-            StatusCode::UNAUTHORIZED => {
-                Err(io::Error::new(io::ErrorKind::PermissionDenied, "the request was not authorized").into())
+            StatusCode::NOT_MODIFIED => {
+                let krate = self.crate_from_cache(name)?;
+                if write_cache_entry {
+                    // Like a fresh entry, the revalidated one is considered new by `cache_entry_info`
+                    if let Some(cache_path) = self.cache_path(name) {
+                        let _ = std::fs::File::options()
+                            .write(true)
+                            .open(cache_path)
+                            .and_then(|file| file.set_modified(std::time::SystemTime::now()));
+                    }
+                }
+                Ok(Some(krate))
             }
             // The crate does not exist, or has been removed
-            StatusCode::NOT_FOUND | StatusCode::GONE | StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS => Ok(None),
-            other => Err(io::Error::new(
-                io::ErrorKind::Unsupported,
-                format!(
-                    "the server responded with status code '{other}', which is not supported in the current protocol"
-                ),
-            )
-            .into()),
+            StatusCode::NOT_FOUND | StatusCode::GONE | StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS => {
+                if write_cache_entry && self.negative_cache_ttl.is_some() {
+                    self.record_missing(name);
+                }
+                Ok(None)
+            }
+            _ => Err(status_error(&parts, Some(name))),
+        }
+    }
+}
+
+/// Reads the local cache, so crates and the configuration are `None` until they were fetched, and crates
+/// [known to be missing](SparseIndex::set_negative_cache_ttl()) are `None` as well.
+impl Index for SparseIndex {
+    fn crate_(&self, name: &str) -> Result<Option<Crate>, Error> {
+        if self.cache_path(name).is_none() {
+            return Ok(None);
+        }
+        match self.crate_from_cache(name) {
+            Ok(krate) => Ok(Some(krate)),
+            Err(Error::KnownMissing { .. }) => Ok(None),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn index_config(&self) -> Result<Option<IndexConfig>, Error> {
+        self.try_index_config()
+    }
+
+    /// Sparse registries can't list their crates, and [`SparseIndex::cached_crates()`] only sees those fetched before.
+    fn crates(&self) -> Option<Box<dyn Iterator<Item = Crate> + '_>> {
+        None
+    }
+
+    fn url(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.url)
+    }
+
+    fn last_updated(&self, name: &str) -> Result<Option<SystemTime>, Error> {
+        if self.cache_path(name).is_none() {
+            return Ok(None);
+        }
+        match self.cache_entry_info(name) {
+            Ok(info) => Ok(Some(info.modified())),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
         }
     }
 }
 
+/// Produce the version of a response to store along with its body, or `None` if the server provided no validator.
+#[cfg(feature = "sparse")]
+fn response_version(headers: &http::HeaderMap) -> Option<String> {
+    use http::header;
+
+    // The same as cargo, prefer etag over last-modified
+    if let Some(etag) = headers.get(header::ETAG) {
+        etag.to_str().ok().map(|etag| format!("{}: {etag}", header::ETAG))
+    } else if let Some(lm) = headers.get(header::LAST_MODIFIED) {
+        lm.to_str().ok().map(|lm| format!("{}: {lm}", header::LAST_MODIFIED))
+    } else {
+        None
+    }
+}
+
+/// Classify a response with a status that isn't part of the protocol for the crate `crate_name`, or the configuration.
+#[cfg(feature = "sparse")]
+fn status_error(parts: &http::response::Parts, crate_name: Option<&str>) -> Error {
+    use http::StatusCode;
+
+    let crate_name = crate_name.map(ToOwned::to_owned);
+    match parts.status {
+        // The server requires authorization but the user didn't provide it
+        StatusCode::UNAUTHORIZED => Error::Unauthorized { crate_name },
+        status => Error::HttpStatus {
+            status: status.as_u16(),
+            crate_name,
+            retry_after: parts
+                .headers
+                .get(http::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after),
+            retryable: status == StatusCode::REQUEST_TIMEOUT
+                || status == StatusCode::TOO_MANY_REQUESTS
+                || status.is_server_error(),
+        },
+    }
+}
+
+/// Parse the value of a `Retry-After` header, which is either an amount of seconds or an HTTP date.
+///
+/// Dates in the past result in a zero duration.
+#[cfg(feature = "sparse")]
+fn parse_retry_after(value: &str) -> Option<std::time::Duration> {
+    let value = value.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(std::time::Duration::from_secs(secs)),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            Some(date.duration_since(std::time::SystemTime::now()).unwrap_or_default())
+        }
+    }
+}
+
+/// Iterator over all crates in the local cache of a sparse index, see [`SparseIndex::cached_crates()`].
+pub struct CachedCrates {
+    paths: cache::Walk,
+}
+
+impl Iterator for CachedCrates {
+    type Item = (Crate, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.paths.by_ref().find_map(|path| read_cache_entry(&path))
+    }
+}
+
+fn read_cache_entry(path: &Path) -> Option<(Crate, String)> {
+    let bytes = std::fs::read(path).ok()?;
+    Crate::from_cache_slice_with_version(&bytes).ok()
+}
+
 #[cfg(test)]
 #[cfg(feature = "sparse")]
 mod tests {
@@ -0,0 +1,122 @@
+use crate::{Crate, Error, IndexConfig, SparseIndex};
+use futures_util::stream::{self, Stream, StreamExt};
+use std::future::Future;
+
+impl SparseIndex {
+    /// Fetches the index entry of the crate `name` from the remote index, writes it into the
+    /// local cache and returns it, or `None` if the crate does not exist in the registry.
+    ///
+    /// This is the async equivalent of [`Self::fetch_crate()`], and like it sends the version of the
+    /// local cache entry along so unchanged crates are read from disk.
+    ///
+    /// Failed requests are retried according to the [retry policy](Self::set_retry_policy()).
+    /// Crates that are [known to be missing](Self::set_negative_cache_ttl()) are not requested.
+    pub async fn fetch_crate_async(&self, name: &str) -> Result<Option<Crate>, Error> {
+        if self.is_known_missing(name) {
+            return Ok(None);
+        }
+        self.with_retries_async(|| async {
+            let response = self.send_async(self.make_cache_request(name)?).await?;
+            let name = name.to_owned();
+            self.blocking(move |index| index.parse_cache_response(&name, response, true))
+                .await
+        })
+        .await
+    }
+
+    /// Fetches the configuration of the remote index, writes it to disk and returns it.
+    ///
+    /// This is the async equivalent of [`Self::fetch_config()`].
+    pub async fn fetch_config_async(&self) -> Result<IndexConfig, Error> {
+        self.with_retries_async(|| async {
+            let response = self.send_async(self.make_config_request()?).await?;
+            self.blocking(move |index| index.parse_config_response(response, true))
+                .await
+        })
+        .await
+    }
+
+    /// Fetches the index entries of all crates in `names` like [`Self::fetch_crate_async()`] does,
+    /// with at most `concurrency` requests in flight at any time.
+    ///
+    /// The returned stream yields each name along with its result in the order in which the requests complete.
+    /// All requests share the same client, so connections to the index are reused, and with HTTP/2 the requests
+    /// are multiplexed over a single connection.
+    pub fn fetch_crates_async<I>(
+        &self,
+        names: I,
+        concurrency: usize,
+    ) -> impl Stream<Item = (String, Result<Option<Crate>, Error>)> + '_
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let names: Vec<String> = names.into_iter().map(Into::into).collect();
+        stream::iter(names)
+            .map(move |name| async move {
+                let res = self.fetch_crate_async(&name).await;
+                (name, res)
+            })
+            .buffer_unordered(concurrency.max(1))
+    }
+
+    async fn with_retries_async<T, F, Fut>(&self, mut attempt: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut retries = 0;
+        loop {
+            match attempt().await {
+                Err(err) => match self.retry_policy.retry_delay(retries, &err) {
+                    Some(delay) => {
+                        tokio::time::sleep(delay).await;
+                        retries += 1;
+                    }
+                    None => return Err(err),
+                },
+                res => return res,
+            }
+        }
+    }
+
+    /// Run `f` on a thread for blocking operations, as processing responses may wait for the
+    /// [package cache lock](crate::lock::PackageCacheLock) and writes to the cache, which must not stall the runtime.
+    async fn blocking<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&SparseIndex) -> Result<T, Error> + Send + 'static,
+    {
+        let mut index = SparseIndex::at_path(self.path.clone(), self.url.clone());
+        index.negative_cache_ttl = self.negative_cache_ttl;
+        index.token.clone_from(&self.token);
+        index.config = self.config.clone();
+        match tokio::task::spawn_blocking(move || f(&index)).await {
+            Ok(res) => res,
+            Err(err) => match err.try_into_panic() {
+                Ok(panic) => std::panic::resume_unwind(panic),
+                Err(err) => Err(std::io::Error::other(err).into()),
+            },
+        }
+    }
+
+    async fn send_async(&self, request: http::request::Builder) -> Result<http::Response<Vec<u8>>, Error> {
+        // `reqwest` negotiates HTTP/2 on its own, but would refuse requests demanding it on HTTP/1.1 connections
+        let request = request
+            .version(http::Version::default())
+            .body(Vec::new())
+            .map_err(|err| Error::Url(err.to_string()))?;
+
+        let response = self.client().execute(request.try_into()?).await?;
+        let mut out = http::Response::new(Vec::new());
+        *out.status_mut() = response.status();
+        *out.version_mut() = response.version();
+        *out.headers_mut() = response.headers().clone();
+        *out.body_mut() = response.bytes().await?.into();
+        Ok(out)
+    }
+
+    fn client(&self) -> &reqwest::Client {
+        self.client.get_or_init(reqwest::Client::new)
+    }
+}
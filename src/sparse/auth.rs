@@ -0,0 +1,38 @@
+use crate::cargo_config::{self, CargoConfig};
+use crate::Error;
+use serde_derive::Deserialize;
+use std::path::Path;
+
+/// A token for the `Authorization` header, which is kept out of `Debug` output.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct AuthToken(pub(crate) String);
+
+impl std::fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AuthToken(<redacted>)")
+    }
+}
+
+/// Get the token cargo's built-in `cargo:token` credential provider would use for the registry `registry_name`,
+/// or for crates.io if it is `None`.
+///
+/// Like cargo, the token is read from the `CARGO_REGISTRIES_<NAME>_TOKEN` environment variable (`CARGO_REGISTRY_TOKEN`
+/// for crates.io), falling back to `registries.<name>.token` (`registry.token` for crates.io) in the credentials file
+/// `credentials` (or `credentials.toml`) in `cargo_home`, and then in the [cargo configuration](CargoConfig) of the
+/// current directory.
+/// If `cargo_home` is not specified, `CARGO_HOME` or else the default cargo location is used.
+///
+/// Note that other credential providers configured in cargo's configuration are not supported.
+///
+/// See <https://doc.rust-lang.org/cargo/reference/registry-authentication.html>
+pub fn cargo_token(registry_name: Option<&str>, cargo_home: Option<&Path>) -> Result<Option<String>, Error> {
+    let cargo_home = match cargo_home {
+        Some(path) => path.to_owned(),
+        None => home::cargo_home()?,
+    };
+    let config = CargoConfig::discover_at(std::env::current_dir()?, cargo_home)?;
+    Ok(config
+        .registry_token(registry_name.unwrap_or(cargo_config::CRATES_IO))
+        .map(ToOwned::to_owned))
+}
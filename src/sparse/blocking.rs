@@ -0,0 +1,80 @@
+use crate::{Crate, Error, IndexConfig, SparseIndex};
+
+/// Index entries of the largest crates are a few megabytes, but `ureq` would stop reading at 10MB by default.
+const MAX_RESPONSE_BYTES: u64 = 128 * 1024 * 1024;
+
+impl SparseIndex {
+    /// Fetches the index entry of the crate `name` from the remote index, writes it into the
+    /// local cache and returns it, or `None` if the crate does not exist in the registry.
+    ///
+    /// If there is a local cache entry, its version is sent along so that the server
+    /// can answer with *Not Modified* and the crate is read from disk instead.
+    /// `gzip` compressed responses are decompressed automatically.
+    ///
+    /// This performs a blocking HTTP/1.1 request, and it's the equivalent of sending the request made by
+    /// [`Self::make_cache_request()`] and passing the response to [`Self::parse_cache_response()`].
+    ///
+    /// Failed requests are retried according to the [retry policy](Self::set_retry_policy()).
+    /// Crates that are [known to be missing](Self::set_negative_cache_ttl()) are not requested.
+    pub fn fetch_crate(&self, name: &str) -> Result<Option<Crate>, Error> {
+        if self.is_known_missing(name) {
+            return Ok(None);
+        }
+        self.with_retries(|| {
+            let response = self.send(self.make_cache_request(name)?)?;
+            self.parse_cache_response(name, response, true)
+        })
+    }
+
+    /// Fetches the configuration of the remote index, writes it to disk and returns it.
+    ///
+    /// This performs a blocking HTTP/1.1 request, and it's the equivalent of sending the request made by
+    /// [`Self::make_config_request()`] and passing the response to [`Self::parse_config_response()`].
+    ///
+    /// Failed requests are retried according to the [retry policy](Self::set_retry_policy()).
+    pub fn fetch_config(&self) -> Result<IndexConfig, Error> {
+        self.with_retries(|| {
+            let response = self.send(self.make_config_request()?)?;
+            self.parse_config_response(response, true)
+        })
+    }
+
+    fn with_retries<T>(&self, mut attempt: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+        let mut retries = 0;
+        loop {
+            match attempt() {
+                Err(err) => match self.retry_policy.retry_delay(retries, &err) {
+                    Some(delay) => {
+                        std::thread::sleep(delay);
+                        retries += 1;
+                    }
+                    None => return Err(err),
+                },
+                res => return res,
+            }
+        }
+    }
+
+    fn send(&self, request: http::request::Builder) -> Result<http::Response<Vec<u8>>, Error> {
+        // `ureq` doesn't support HTTP/2, so we have to downgrade the request to HTTP/1.1
+        let request = request
+            .version(http::Version::HTTP_11)
+            .body(())
+            .map_err(ureq::Error::from)?;
+
+        let response = self.agent().run(request)?;
+        let (parts, mut body) = response.into_parts();
+        let body = body.with_config().limit(MAX_RESPONSE_BYTES).read_to_vec()?;
+        Ok(http::Response::from_parts(parts, body))
+    }
+
+    fn agent(&self) -> &ureq::Agent {
+        self.agent.get_or_init(|| {
+            ureq::Agent::config_builder()
+                // Statuses like 304 and 404 are part of the protocol and handled when parsing the response
+                .http_status_as_error(false)
+                .build()
+                .new_agent()
+        })
+    }
+}
@@ -0,0 +1,234 @@
+use crate::{Crate, DependencyKind, Error, SparseIndex};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// A state machine to obtain the dependency closure of a set of root crates from a sparse index,
+/// the same way Cargo discovers crates when resolving.
+///
+/// It doesn't perform any IO itself. Instead, [`Crawler::next_requests()`] produces the requests to send
+/// with the HTTP client of choice, and their responses are handed back via [`Crawler::parse_response()`],
+/// which queues the dependencies of the received crate that haven't been seen yet.
+/// Repeat until [`Crawler::is_done()`].
+///
+/// ```no_run
+/// # fn send(_req: http::request::Builder) -> http::Response<Vec<u8>> { unimplemented!() }
+/// let index = crates_index::SparseIndex::new_cargo_default()?;
+/// let mut crawler = crates_index::sparse::Crawler::new(["serde", "anyhow"]);
+/// while !crawler.is_done() {
+///     for (name, request) in crawler.next_requests(&index, 20)? {
+///         // Usually these would be sent concurrently.
+///         crawler.parse_response(&index, &name, send(request), true)?;
+///     }
+/// }
+/// println!("{} crates in the closure", crawler.crates().len());
+/// # Ok::<_, crates_index::Error>(())
+/// ```
+///
+/// Dependencies of all versions of a crate are followed, so the closure is a superset of what a resolver would pick.
+/// Dependencies on other registries are ignored.
+#[derive(Debug, Clone)]
+pub struct Crawler {
+    kinds: Vec<DependencyKind>,
+    include_yanked: bool,
+    /// Names to request next, in the order they were discovered
+    queue: VecDeque<String>,
+    /// Lowercase names of all crates that were ever queued
+    seen: HashSet<String>,
+    /// Lowercase names of crates for which a request is out
+    in_flight: HashSet<String>,
+    crates: Vec<Crate>,
+    /// Lowercase names of all received crates, with their position in `crates`
+    received: HashMap<String, usize>,
+    missing: Vec<String>,
+    /// Names of crates whose response couldn't be processed due to a permanent error
+    failed: Vec<String>,
+}
+
+impl Crawler {
+    /// Create a new crawler that starts at the `roots` crate names.
+    ///
+    /// By default, normal and build dependencies of non-yanked versions are followed.
+    pub fn new<I>(roots: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let mut crawler = Self {
+            kinds: vec![DependencyKind::Normal, DependencyKind::Build],
+            include_yanked: false,
+            queue: VecDeque::new(),
+            seen: HashSet::new(),
+            in_flight: HashSet::new(),
+            crates: Vec::new(),
+            received: HashMap::new(),
+            missing: Vec::new(),
+            failed: Vec::new(),
+        };
+        for name in roots {
+            crawler.enqueue(name.into());
+        }
+        crawler
+    }
+
+    /// Only follow dependencies of the given `kinds`.
+    #[must_use]
+    pub fn dependency_kinds(mut self, kinds: &[DependencyKind]) -> Self {
+        self.kinds = kinds.to_vec();
+        self
+    }
+
+    /// If `yes`, also follow the dependencies of yanked versions.
+    #[must_use]
+    pub fn include_yanked(mut self, yes: bool) -> Self {
+        self.include_yanked = yes;
+        self
+    }
+
+    /// Produce requests for up to `max` crates that are yet to be fetched, along with their names.
+    ///
+    /// The requests are made with [`SparseIndex::make_cache_request()`], so they are conditional if
+    /// there is a local cache entry. Their responses must be passed to [`Self::parse_response()`].
+    /// Crates which the index knows to be missing are not requested, and are added to [`Self::missing()`] right away.
+    pub fn next_requests(
+        &mut self,
+        index: &SparseIndex,
+        max: usize,
+    ) -> Result<Vec<(String, http::request::Builder)>, Error> {
+        let mut out = Vec::with_capacity(max.min(self.queue.len()));
+        while out.len() < max {
+            let Some(name) = self.queue.pop_front() else {
+                break;
+            };
+            let request = match index.make_cache_request(&name) {
+                Ok(request) => request,
+                Err(Error::KnownMissing { .. }) => {
+                    self.missing.push(name);
+                    continue;
+                }
+                Err(err) => {
+                    self.queue.push_front(name);
+                    return Err(err);
+                }
+            };
+            self.in_flight.insert(name.to_ascii_lowercase());
+            out.push((name, request));
+        }
+        Ok(out)
+    }
+
+    /// Process the `response` to the request for the crate `name` with [`SparseIndex::parse_cache_response()`],
+    /// and queue all of its dependencies that weren't seen before.
+    ///
+    /// The received crate is returned, or `None` if it doesn't exist in the index.
+    /// If the error is temporary (see [`Error::is_retryable()`]), the crate is queued again to be requested
+    /// by a later call to [`Self::next_requests()`], ideally after waiting as a
+    /// [`RetryPolicy`](super::RetryPolicy) suggests. Otherwise it's added to [`Self::failed()`] and not requested again.
+    pub fn parse_response(
+        &mut self,
+        index: &SparseIndex,
+        name: &str,
+        response: http::Response<Vec<u8>>,
+        write_cache_entry: bool,
+    ) -> Result<Option<&Crate>, Error> {
+        self.in_flight.remove(&name.to_ascii_lowercase());
+        match index.parse_cache_response(name, response, write_cache_entry) {
+            Ok(Some(krate)) => Ok(Some(self.add_crate(krate))),
+            Ok(None) => {
+                self.missing.push(name.to_owned());
+                Ok(None)
+            }
+            Err(err) => {
+                if err.is_retryable() {
+                    self.queue.push_back(name.to_owned());
+                } else {
+                    self.failed.push(name.to_owned());
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Add a crate that was obtained by other means, for instance from the local cache, and queue its dependencies.
+    ///
+    /// This is useful to avoid requests for crates that are known to be fresh.
+    /// A crate that was received before is replaced.
+    pub fn add_crate(&mut self, krate: Crate) -> &Crate {
+        let name = krate.name().to_ascii_lowercase();
+        self.in_flight.remove(&name);
+        if !self.seen.insert(name.clone()) {
+            // It may still be queued, but there is no need to request it anymore.
+            self.queue.retain(|queued| !queued.eq_ignore_ascii_case(&name));
+        }
+
+        let mut discovered = Vec::new();
+        for version in krate.versions() {
+            if version.is_yanked() && !self.include_yanked {
+                continue;
+            }
+            for dep in version.dependencies() {
+                if dep.registry().is_some() || !self.kinds.contains(&dep.kind()) {
+                    continue;
+                }
+                discovered.push(dep.crate_name().to_owned());
+            }
+        }
+        for name in discovered {
+            self.enqueue(name);
+        }
+
+        match self.received.get(&name) {
+            Some(&pos) => {
+                self.crates[pos] = krate;
+                &self.crates[pos]
+            }
+            None => {
+                self.received.insert(name, self.crates.len());
+                self.crates.push(krate);
+                self.crates.last().expect("just pushed")
+            }
+        }
+    }
+
+    /// Returns `true` if there is nothing left to request and no response is outstanding.
+    ///
+    /// The closure is only complete if no crate [failed](Self::failed()) as well.
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.queue.is_empty() && self.in_flight.is_empty()
+    }
+
+    /// The amount of crates that still have to be requested, not counting requests that are in flight.
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// All crates received so far.
+    #[must_use]
+    pub fn crates(&self) -> &[Crate] {
+        &self.crates
+    }
+
+    /// Names of all crates which the index reported as non-existing.
+    #[must_use]
+    pub fn missing(&self) -> &[String] {
+        &self.missing
+    }
+
+    /// Names of all crates whose response failed with an error that isn't temporary, so the closure is incomplete.
+    #[must_use]
+    pub fn failed(&self) -> &[String] {
+        &self.failed
+    }
+
+    /// Consume the crawler and return all crates received so far.
+    #[must_use]
+    pub fn into_crates(self) -> Vec<Crate> {
+        self.crates
+    }
+
+    fn enqueue(&mut self, name: String) {
+        if self.seen.insert(name.to_ascii_lowercase()) {
+            self.queue.push_back(name);
+        }
+    }
+}
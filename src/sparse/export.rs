@@ -0,0 +1,246 @@
+#[cfg(feature = "sparse")]
+use super::IndexSource;
+use crate::dirs::{crate_name_to_relative_path, relative_path_to_crate_name};
+use crate::{cache, Crate, Error, IndexConfig};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Writes the configuration and crates of an index to a directory with the layout of a registry index,
+/// like `se/rd/serde` or `3/a/abc`, so that any static web server can serve it as `sparse+` registry.
+///
+/// ```no_run
+/// # #[cfg(all(feature = "git", feature = "sparse"))]
+/// # {
+/// let index = crates_index::GitIndex::new_cargo_default()?;
+/// let names = index.crates().map(|krate| krate.name().to_owned());
+/// let report = crates_index::sparse::Exporter::new("mirror")
+///     .remove_stale(true)
+///     .export_from(&index, names)?;
+/// println!("{} crates changed", report.written);
+/// # }
+/// # Ok::<_, crates_index::Error>(())
+/// ```
+///
+/// Files of an [`IndexSource`](super::IndexSource) are copied as they are, while crates that were built in memory are
+/// serialized with [`Crate::to_index_lines()`], which drops fields of the index format this crate doesn't know.
+///
+/// By default the export is incremental, so files whose content didn't change aren't written again,
+/// which keeps their modification time and thus the `Last-Modified` date of web servers intact.
+/// All files are replaced atomically, so they can be served while the export is running.
+#[derive(Debug, Clone)]
+pub struct Exporter {
+    path: PathBuf,
+    incremental: bool,
+    remove_stale: bool,
+}
+
+/// The outcome of [`Exporter::export()`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExportReport {
+    /// The amount of crates whose index file was written.
+    pub written: usize,
+    /// The amount of crates whose index file already had the exported content.
+    pub unchanged: usize,
+    /// The index files of crates that weren't exported, and that were removed, see [`Exporter::remove_stale()`].
+    pub removed: Vec<PathBuf>,
+    /// `true` if `config.json` was written.
+    pub config_written: bool,
+}
+
+impl Exporter {
+    /// Export to the directory at `path`, which is created if needed.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            incremental: true,
+            remove_stale: false,
+        }
+    }
+
+    /// If `yes`, which is the default, only write files whose content changed, otherwise write all of them.
+    #[must_use]
+    pub fn incremental(mut self, yes: bool) -> Self {
+        self.incremental = yes;
+        self
+    }
+
+    /// If `yes`, remove the index files of all crates that are not part of an export, as they were
+    /// removed from the source. Other files in the directory are never touched.
+    #[must_use]
+    pub fn remove_stale(mut self, yes: bool) -> Self {
+        self.remove_stale = yes;
+        self
+    }
+
+    /// The directory the index is written to.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write `config` as `config.json` along with the index files of all `crates`, and report what changed.
+    ///
+    /// Crates without versions are skipped as they have no index file, and if multiple crates have the
+    /// same name ignoring case, the last one is written.
+    pub fn export(&self, config: &IndexConfig, crates: impl IntoIterator<Item = Crate>) -> Result<ExportReport, Error> {
+        let mut report = ExportReport {
+            config_written: self.write_config(config)?,
+            ..Default::default()
+        };
+        let mut exported = HashSet::new();
+        for krate in crates {
+            let Some(first) = krate.versions().first() else {
+                continue;
+            };
+            exported.insert(first.name().to_ascii_lowercase());
+            if self.write_crate(&krate)? {
+                report.written += 1;
+            } else {
+                report.unchanged += 1;
+            }
+        }
+        if self.remove_stale {
+            report.removed = self.remove_crates_except(&exported)?;
+        }
+        Ok(report)
+    }
+
+    /// Copy `config.json` along with the index files of the crates `names` from `source`, and report what changed.
+    ///
+    /// The files are copied byte for byte, so unlike [`Self::export()`] nothing is lost that this crate
+    /// doesn't know about. Crates that `source` doesn't have are skipped, as is the configuration if there is none.
+    #[cfg(feature = "sparse")]
+    pub fn export_from<S, I>(&self, source: &S, names: I) -> Result<ExportReport, Error>
+    where
+        S: IndexSource + ?Sized,
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let mut report = ExportReport {
+            config_written: match source.config_file()? {
+                Some(contents) => self.write(&self.path.join(super::CONFIG_FILE), &contents)?,
+                None => false,
+            },
+            ..Default::default()
+        };
+        let mut exported = HashSet::new();
+        for name in names {
+            let name = name.as_ref().to_ascii_lowercase();
+            let Some(rel_path) = crate_name_to_relative_path(&name, None) else {
+                continue;
+            };
+            let Some(contents) = source.crate_file(&name)? else {
+                continue;
+            };
+            if self.write(&self.path.join(rel_path), &contents)? {
+                report.written += 1;
+            } else {
+                report.unchanged += 1;
+            }
+            exported.insert(name);
+        }
+        if self.remove_stale {
+            report.removed = self.remove_crates_except(&exported)?;
+        }
+        Ok(report)
+    }
+
+    /// Write `config` as `config.json`, and return `true` if it was written.
+    pub fn write_config(&self, config: &IndexConfig) -> Result<bool, Error> {
+        let contents = serde_json::to_vec(config)?;
+        self.write(&self.path.join(super::CONFIG_FILE), &contents)
+    }
+
+    /// Write the index file of `krate`, and return `true` if it was written.
+    ///
+    /// The file is serialized with [`Crate::to_index_lines()`], use [`Self::copy_crate()`] to keep the original lines.
+    pub fn write_crate(&self, krate: &Crate) -> Result<bool, Error> {
+        let Some(rel_path) = krate
+            .versions()
+            .first()
+            .and_then(|first| crate_name_to_relative_path(first.name(), None))
+        else {
+            return Ok(false);
+        };
+        self.write(&self.path.join(rel_path), &krate.to_index_lines())
+    }
+
+    /// Copy the index file of the crate `name` from `source` as it is, and return `true` if it was written.
+    ///
+    /// This allows to update single crates, for example after they changed in a [`GitIndex`](crate::GitIndex).
+    /// Nothing is written if `source` doesn't have the crate.
+    #[cfg(feature = "sparse")]
+    pub fn copy_crate<S: IndexSource + ?Sized>(&self, source: &S, name: &str) -> Result<bool, Error> {
+        let name = name.to_ascii_lowercase();
+        let Some(rel_path) = crate_name_to_relative_path(&name, None) else {
+            return Ok(false);
+        };
+        match source.crate_file(&name)? {
+            Some(contents) => self.write(&self.path.join(rel_path), &contents),
+            None => Ok(false),
+        }
+    }
+
+    /// Remove the index file of the crate `name`, and return `true` if there was one.
+    pub fn remove_crate(&self, name: &str) -> Result<bool, Error> {
+        let Some(rel_path) = crate_name_to_relative_path(name, None) else {
+            return Ok(false);
+        };
+        let path = self.path.join(rel_path);
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                super::gc::remove_empty_parents(&path, &self.path);
+                Ok(true)
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<bool, Error> {
+        if self.incremental && std::fs::read(path).is_ok_and(|existing| existing == contents) {
+            return Ok(false);
+        }
+        std::fs::create_dir_all(path.parent().expect("index files are in a directory"))?;
+        cache::write_atomic(path, contents)?;
+        Ok(true)
+    }
+
+    /// Remove all index files of crates whose lowercase names are not in `keep`, and return their paths.
+    fn remove_crates_except(&self, keep: &HashSet<String>) -> Result<Vec<PathBuf>, Error> {
+        let mut removed = Vec::new();
+        let Ok(entries) = std::fs::read_dir(&self.path) else {
+            return Ok(removed);
+        };
+        for entry in entries {
+            let dir = entry?.path();
+            // Skip files like `config.json` and hidden directories like `.git`, which can't contain index files
+            if !dir.is_dir()
+                || dir
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+            {
+                continue;
+            }
+            for path in cache::Walk::new(&dir) {
+                let Some(rel_path) = path
+                    .strip_prefix(&self.path)
+                    .ok()
+                    .and_then(Path::to_str)
+                    .map(|rel_path| rel_path.replace(std::path::MAIN_SEPARATOR, "/"))
+                else {
+                    continue;
+                };
+                if relative_path_to_crate_name(&rel_path).is_some_and(|name| !keep.contains(&name)) {
+                    removed.push(path);
+                }
+            }
+        }
+        // Remove only after walking, as directories may be removed along with their last file
+        for path in &removed {
+            std::fs::remove_file(path)?;
+            super::gc::remove_empty_parents(path, &self.path);
+        }
+        Ok(removed)
+    }
+}
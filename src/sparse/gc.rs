@@ -0,0 +1,201 @@
+use crate::cache::{self, CacheEntryInfo};
+use crate::lock::{LockMode, PackageCacheLock};
+use crate::{Error, SparseIndex};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Decides which entries [`SparseIndex::gc()`] removes from the local cache of an index.
+///
+/// The default policy removes nothing, so at least one criterion has to be set.
+/// Entries are considered used when they were last written or revalidated with the server,
+/// as the modification time of the cache entries tells, see [`CacheEntryInfo::modified()`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GcPolicy {
+    max_age: Option<Duration>,
+    max_total_size: Option<u64>,
+    remove_unsupported: bool,
+    dry_run: bool,
+}
+
+impl GcPolicy {
+    /// Remove entries that were last used longer than `age` ago.
+    #[must_use]
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Remove the least recently used entries until all remaining ones take at most `bytes` on disk.
+    #[must_use]
+    pub fn max_total_size(mut self, bytes: u64) -> Self {
+        self.max_total_size = Some(bytes);
+        self
+    }
+
+    /// If `yes`, remove entries that can't be read, or that have a format that cargo doesn't write anymore
+    /// (see [`CacheEntryInfo::is_current()`]), and which cargo would replace anyway.
+    #[must_use]
+    pub fn remove_unsupported(mut self, yes: bool) -> Self {
+        self.remove_unsupported = yes;
+        self
+    }
+
+    /// If `yes`, only report what would be removed without touching the cache.
+    #[must_use]
+    pub fn dry_run(mut self, yes: bool) -> Self {
+        self.dry_run = yes;
+        self
+    }
+}
+
+/// The reason for removing a cache entry, see [`GcPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcReason {
+    /// The entry wasn't used within [`GcPolicy::max_age()`].
+    Expired,
+    /// The entry was among the least recently used ones when the cache exceeded [`GcPolicy::max_total_size()`].
+    SizeLimit,
+    /// The entry couldn't be read or has an outdated format, see [`GcPolicy::remove_unsupported()`].
+    Unsupported,
+}
+
+/// A cache entry that was removed by [`SparseIndex::gc()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcEntry {
+    /// The path to the cache file.
+    pub path: PathBuf,
+    /// The size of the cache file in bytes.
+    pub size: u64,
+    /// Why the entry was removed.
+    pub reason: GcReason,
+}
+
+/// The outcome of [`SparseIndex::gc()`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GcReport {
+    /// The entries that were removed, or would have been removed in a dry run.
+    pub removed: Vec<GcEntry>,
+    /// The amount of entries that remain in the cache.
+    pub remaining_entries: usize,
+    /// The size of all remaining entries in bytes.
+    pub remaining_bytes: u64,
+    /// `true` if nothing was actually removed, see [`GcPolicy::dry_run()`].
+    pub dry_run: bool,
+}
+
+impl GcReport {
+    /// The size of all removed entries in bytes.
+    #[must_use]
+    pub fn removed_bytes(&self) -> u64 {
+        self.removed.iter().map(|entry| entry.size).sum()
+    }
+}
+
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+impl SparseIndex {
+    /// Remove entries from the local cache of the index according to `policy`, and report what was removed.
+    ///
+    /// Like `cargo clean gc`, this holds the [package cache lock](PackageCacheLock) of cargo exclusively
+    /// while deleting if the index is located in a cargo home, so it waits for running cargo invocations to
+    /// finish reading the index. Removed entries are simply fetched again when they are needed next time.
+    pub fn gc(&self, policy: GcPolicy) -> Result<GcReport, Error> {
+        let _lock = if policy.dry_run {
+            None
+        } else {
+            PackageCacheLock::for_index(&self.path, LockMode::MutateExclusive)?
+        };
+        let cache_dir = self.path.join(cache::CACHE_DIR);
+        let now = SystemTime::now();
+
+        let mut report = GcReport {
+            dry_run: policy.dry_run,
+            ..Default::default()
+        };
+        let mut kept = Vec::new();
+        for path in cache::Walk::new(&cache_dir) {
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+            let candidate = Candidate {
+                path,
+                size: metadata.len(),
+                modified: metadata.modified()?,
+            };
+            let reason = if policy.remove_unsupported
+                && !CacheEntryInfo::read(&candidate.path).is_ok_and(|info| info.is_current())
+            {
+                Some(GcReason::Unsupported)
+            } else if policy
+                .max_age
+                .is_some_and(|max_age| now.duration_since(candidate.modified).unwrap_or_default() > max_age)
+            {
+                Some(GcReason::Expired)
+            } else {
+                None
+            };
+            match reason {
+                Some(reason) => report.removed.push(remove(candidate, reason, policy.dry_run)?),
+                None => kept.push(candidate),
+            }
+        }
+
+        let mut remaining_bytes: u64 = kept.iter().map(|candidate| candidate.size).sum();
+        if let Some(max_total_size) = policy.max_total_size {
+            // Least recently used first, with ties broken by path for reproducible results
+            kept.sort_by(|a, b| a.modified.cmp(&b.modified).then_with(|| a.path.cmp(&b.path)));
+            let mut lru = kept.into_iter();
+            kept = Vec::new();
+            for candidate in lru.by_ref() {
+                if remaining_bytes <= max_total_size {
+                    kept.push(candidate);
+                    break;
+                }
+                remaining_bytes -= candidate.size;
+                report
+                    .removed
+                    .push(remove(candidate, GcReason::SizeLimit, policy.dry_run)?);
+            }
+            kept.extend(lru);
+        }
+
+        if !policy.dry_run {
+            for entry in &report.removed {
+                remove_empty_parents(&entry.path, &cache_dir);
+            }
+        }
+        report.remaining_entries = kept.len();
+        report.remaining_bytes = remaining_bytes;
+        Ok(report)
+    }
+}
+
+fn remove(candidate: Candidate, reason: GcReason, dry_run: bool) -> Result<GcEntry, Error> {
+    if !dry_run {
+        match std::fs::remove_file(&candidate.path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    Ok(GcEntry {
+        path: candidate.path,
+        size: candidate.size,
+        reason,
+    })
+}
+
+/// Remove the directories containing `path` as long as they are empty, up to but excluding `root`.
+pub(super) fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir.filter(|dir| *dir != root && dir.starts_with(root)) {
+        // This fails if the directory isn't empty, which is when we are done.
+        if std::fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
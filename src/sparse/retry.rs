@@ -0,0 +1,149 @@
+use crate::{CargoConfig, Error};
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Decides whether and when a failed request to a sparse index should be sent again.
+///
+/// It doesn't perform any IO or sleep itself, so it can be used with any HTTP client.
+/// After an attempt failed with `err`, ask for the delay with [`RetryPolicy::retry_delay()`],
+/// wait for it and resend the request, or give up if there is none.
+///
+/// ```no_run
+/// # fn send(_req: http::request::Builder) -> http::Response<Vec<u8>> { unimplemented!() }
+/// let index = crates_index::SparseIndex::new_cargo_default()?;
+/// let policy = crates_index::sparse::RetryPolicy::default();
+/// let mut retries = 0;
+/// let krate = loop {
+///     let response = send(index.make_cache_request("serde")?);
+///     match index.parse_cache_response("serde", response, true) {
+///         Err(err) => match policy.retry_delay(retries, &err) {
+///             Some(delay) => {
+///                 std::thread::sleep(delay);
+///                 retries += 1;
+///             }
+///             None => return Err(err),
+///         },
+///         Ok(krate) => break krate,
+///     }
+/// };
+/// # Ok::<_, crates_index::Error>(())
+/// ```
+///
+/// Like cargo's [`net.retry`](https://doc.rust-lang.org/cargo/reference/config.html#netretry), requests are retried
+/// 3 times by default, and only if the error is temporary (see [`Error::is_retryable()`]).
+/// The delay grows exponentially with each retry, is randomized to spread the load on the server,
+/// and if the server asked for a specific delay with the `Retry-After` header, it is honored instead,
+/// up to [`RetryPolicy::max_retry_after()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    max_retry_after: Duration,
+    jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            max_retry_after: Duration::from_secs(60),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The default policy, retrying as often as cargo does according to [`net.retry`](crate::cargo_config::NetConfig::retry)
+    /// in `config`.
+    #[must_use]
+    pub fn from_cargo_config(config: &CargoConfig) -> Self {
+        match config.net.retry {
+            Some(retries) => Self::default().max_retries(retries),
+            None => Self::default(),
+        }
+    }
+
+    /// A policy that never retries.
+    #[must_use]
+    pub fn never() -> Self {
+        Self::default().max_retries(0)
+    }
+
+    /// Send a request at most `retries` more times after the first attempt failed.
+    #[must_use]
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Wait `delay` before the first retry, doubling it for each following retry.
+    #[must_use]
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Never wait longer than `delay` between attempts, unless the server asks for it with `Retry-After`,
+    /// see [`Self::max_retry_after()`].
+    #[must_use]
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Never wait longer than `delay` when the server asks for a delay with `Retry-After`, which is 60 seconds
+    /// by default, so a server can't stall the client for an arbitrary time.
+    #[must_use]
+    pub fn max_retry_after(mut self, delay: Duration) -> Self {
+        self.max_retry_after = delay;
+        self
+    }
+
+    /// If `yes`, randomize each delay to be between half and all of the computed backoff.
+    #[must_use]
+    pub fn jitter(mut self, yes: bool) -> Self {
+        self.jitter = yes;
+        self
+    }
+
+    /// The amount of retries after which the policy gives up.
+    #[must_use]
+    pub fn retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Returns how long to wait before sending the request again after it failed with `err`,
+    /// given that it was already retried `retries` times, or `None` if it should not be retried.
+    #[must_use]
+    pub fn retry_delay(&self, retries: u32, err: &Error) -> Option<Duration> {
+        if retries >= self.max_retries || !err.is_retryable() {
+            return None;
+        }
+        if let Error::HttpStatus {
+            retry_after: Some(delay),
+            ..
+        } = err
+        {
+            return Some((*delay).min(self.max_retry_after));
+        }
+        Some(self.backoff(retries))
+    }
+
+    /// The delay before retry number `retries + 1`, without consulting the error.
+    #[must_use]
+    pub fn backoff(&self, retries: u32) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(retries))
+            .min(self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+        let half = delay / 2;
+        let random = std::collections::hash_map::RandomState::new().build_hasher().finish();
+        half + Duration::from_nanos(random % (half.as_nanos() as u64).saturating_add(1))
+    }
+}
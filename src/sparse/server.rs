@@ -0,0 +1,283 @@
+use crate::dirs::{crate_name_to_relative_path, relative_path_to_crate_name};
+use crate::{Crate, Error, IndexConfig};
+use http::{header, HeaderValue, Method, StatusCode};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The files of a registry index that are served by a [`SparseServer`].
+///
+/// Implementations only need to look up files, while validating requests, computing ETags and
+/// answering conditional requests is done by the server.
+pub trait IndexSource {
+    /// Returns the contents of `config.json`, or `None` if the index has no configuration.
+    fn config_file(&self) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Returns the contents of the index file of the crate `name`, which is lowercase, in the format
+    /// of the index with one JSON object per version and line, or `None` if there is no such crate.
+    fn crate_file(&self, name: &str) -> Result<Option<Vec<u8>>, Error>;
+}
+
+impl<S: IndexSource + ?Sized> IndexSource for &S {
+    fn config_file(&self) -> Result<Option<Vec<u8>>, Error> {
+        (**self).config_file()
+    }
+
+    fn crate_file(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        (**self).crate_file(name)
+    }
+}
+
+impl<S: IndexSource + ?Sized> IndexSource for Arc<S> {
+    fn config_file(&self) -> Result<Option<Vec<u8>>, Error> {
+        (**self).config_file()
+    }
+
+    fn crate_file(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        (**self).crate_file(name)
+    }
+}
+
+/// Serves the files of an [`IndexSource`] with the [sparse protocol](https://doc.rust-lang.org/cargo/reference/registry-index.html#sparse-protocol),
+/// so that cargo and [`SparseIndex`](crate::SparseIndex) can use it as `sparse+` registry.
+///
+/// It doesn't perform any IO itself, so it can be used with any HTTP server by passing the requests
+/// it receives to [`SparseServer::handle()`] and sending back the response.
+///
+/// ```
+/// use crates_index::sparse::{MemoryIndex, SparseServer};
+///
+/// let server = SparseServer::new(MemoryIndex::new()).path_prefix("/index");
+/// let request = http::Request::get("/index/se/rd/serde").body(())?;
+/// let response = server.handle(&request)?;
+/// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct SparseServer<S> {
+    source: S,
+    path_prefix: String,
+}
+
+impl<S: IndexSource> SparseServer<S> {
+    /// Serve the files in `source` at the root path.
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            path_prefix: String::new(),
+        }
+    }
+
+    /// Serve the files below `prefix`, like `/index`, and answer all requests outside of it with *Not Found*.
+    #[must_use]
+    pub fn path_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.path_prefix = prefix.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// The source of the files that are served.
+    #[must_use]
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// Mutable access to the source of the files that are served.
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Produce the response to `request` for `config.json` or the index file of a crate, like `se/rd/serde`.
+    ///
+    /// Responses carry an `ETag` derived from their content, and requests with a matching `If-None-Match` header
+    /// are answered with *Not Modified*. Paths that cargo wouldn't request for a crate, and crates that the source
+    /// doesn't have, are answered with *Not Found*, and methods other than `GET` and `HEAD` with *Method Not Allowed*.
+    ///
+    /// Errors only occur if the source fails to provide a file, and are typically answered with
+    /// *Internal Server Error*.
+    pub fn handle<B>(&self, request: &http::Request<B>) -> Result<http::Response<Vec<u8>>, Error> {
+        let is_head = request.method() == Method::HEAD;
+        if !is_head && request.method() != Method::GET {
+            return Ok(http::Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "GET, HEAD")
+                .body(Vec::new())
+                .expect("valid response"));
+        }
+
+        let file = match request
+            .uri()
+            .path()
+            .strip_prefix(self.path_prefix.as_str())
+            .and_then(|path| path.strip_prefix('/'))
+        {
+            Some(super::CONFIG_FILE) => self.source.config_file()?.map(|body| (body, "application/json")),
+            Some(path) => match relative_path_to_crate_name(path) {
+                Some(name) => self.source.crate_file(&name)?.map(|body| (body, "text/plain")),
+                None => None,
+            },
+            None => None,
+        };
+        let Some((body, content_type)) = file else {
+            return Ok(http::Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Vec::new())
+                .expect("valid response"));
+        };
+
+        let etag = etag_of(&body);
+        let not_modified = request
+            .headers()
+            .get_all(header::IF_NONE_MATCH)
+            .iter()
+            .any(|value| etag_matches(value, &etag));
+        let response = http::Response::builder().header(header::ETAG, &etag);
+        let response = if not_modified {
+            response.status(StatusCode::NOT_MODIFIED).body(Vec::new())
+        } else {
+            response
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::CONTENT_LENGTH, body.len())
+                .body(if is_head { Vec::new() } else { body })
+        };
+        Ok(response.expect("valid response"))
+    }
+}
+
+/// A strong ETag that only depends on `body`, so it is the same across restarts and instances of a server.
+fn etag_of(body: &[u8]) -> String {
+    use std::hash::Hasher;
+
+    let mut hasher = rustc_stable_hash::StableSipHasher128::new();
+    hasher.write(body);
+    format!("\"{:016x}\"", Hasher::finish(&hasher))
+}
+
+/// Compare `etag` to the list of tags in an `If-None-Match` header value, using the weak comparison function
+/// as the specification requires.
+fn etag_matches(if_none_match: &HeaderValue, etag: &str) -> bool {
+    let Ok(if_none_match) = if_none_match.to_str() else {
+        return false;
+    };
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag)
+}
+
+/// An [`IndexSource`] for a directory with the layout of a registry index, like a checkout of a git index.
+#[derive(Debug, Clone)]
+pub struct IndexDir {
+    path: PathBuf,
+}
+
+impl IndexDir {
+    /// Serve the index files in the directory at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The directory containing the index files.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self, rel_path: &str) -> Result<Option<Vec<u8>>, Error> {
+        match std::fs::read(self.path.join(rel_path)) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl IndexSource for IndexDir {
+    fn config_file(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.read(super::CONFIG_FILE)
+    }
+
+    fn crate_file(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        match crate_name_to_relative_path(name, None) {
+            Some(rel_path) => self.read(&rel_path),
+            None => Ok(None),
+        }
+    }
+}
+
+/// An [`IndexSource`] holding the configuration and crates of an index in memory.
+///
+/// Crates are serialized when they are inserted, so serving them is cheap.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryIndex {
+    config: Option<Vec<u8>>,
+    /// The index files of all crates by their lowercase name.
+    crates: BTreeMap<String, Vec<u8>>,
+}
+
+impl MemoryIndex {
+    /// An index without configuration and crates.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An index with `config` and all `crates`.
+    pub fn with_crates(config: &IndexConfig, crates: impl IntoIterator<Item = Crate>) -> Self {
+        let mut index = Self::new();
+        index.set_config(config);
+        index.extend(crates);
+        index
+    }
+
+    /// Set the configuration that is served as `config.json`.
+    pub fn set_config(&mut self, config: &IndexConfig) {
+        self.config = Some(serde_json::to_vec(config).expect("serializing the configuration never fails"));
+    }
+
+    /// Add `krate`, replacing a previous crate with the same name, ignoring case.
+    ///
+    /// Crates without versions are ignored, as they have no index file.
+    pub fn insert(&mut self, krate: &Crate) {
+        let Some(first) = krate.versions().first() else {
+            return;
+        };
+        self.crates
+            .insert(first.name().to_ascii_lowercase(), krate.to_index_lines());
+    }
+
+    /// Remove the crate `name`, ignoring case, and return `true` if it was present.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.crates.remove(&name.to_ascii_lowercase()).is_some()
+    }
+
+    /// The amount of crates in the index.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.crates.len()
+    }
+
+    /// Returns `true` if there are no crates in the index.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.crates.is_empty()
+    }
+}
+
+impl Extend<Crate> for MemoryIndex {
+    fn extend<T: IntoIterator<Item = Crate>>(&mut self, crates: T) {
+        for krate in crates {
+            self.insert(&krate);
+        }
+    }
+}
+
+impl IndexSource for MemoryIndex {
+    fn config_file(&self) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.config.clone())
+    }
+
+    fn crate_file(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.crates.get(name).cloned())
+    }
+}
@@ -1,37 +1,67 @@
+#![allow(clippy::result_large_err)]
+
 use crate::dedupe::DedupeContext;
 
-use crate::IndexConfig;
+use crate::{cache, Error, IndexConfig};
 use semver::Version as SemverVersion;
 use serde_derive::{Deserialize, Serialize};
 use smol_str::SmolStr;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 use std::path::Path;
 use std::sync::Arc;
 
 /// A single version of a crate (package) published to the index
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub struct Version {
-    name: SmolStr,
-    vers: SmolStr,
-    deps: Arc<[Dependency]>,
-    features: Arc<HashMap<String, Vec<String>>>,
+    pub(crate) name: SmolStr,
+    pub(crate) vers: SmolStr,
+    pub(crate) deps: Arc<[Dependency]>,
+    pub(crate) features: Arc<HashMap<String, Vec<String>>>,
     /// It's wrapped in `Option<Box>` to reduce size of the struct when the field is unused (i.e. almost always)
     /// <https://rust-lang.github.io/rfcs/3143-cargo-weak-namespaced-features.html#index-changes>
     #[serde(default, skip_serializing_if = "Option::is_none")]
     #[allow(clippy::box_collection)]
-    features2: Option<Box<HashMap<String, Vec<String>>>>,
+    pub(crate) features2: Option<Box<HashMap<String, Vec<String>>>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    links: Option<Box<SmolStr>>,
+    pub(crate) links: Option<Box<SmolStr>>,
     #[serde(default)]
-    rust_version: Option<SmolStr>,
+    pub(crate) rust_version: Option<SmolStr>,
     #[serde(with = "hex")]
-    cksum: [u8; 32],
+    pub(crate) cksum: [u8; 32],
     #[serde(default)]
-    yanked: bool,
+    pub(crate) yanked: bool,
 }
 
 impl Version {
+    /// Start building a version of the crate `name` that isn't parsed from an index, like for fixtures or
+    /// to generate an index, with the SHA256 checksum `cksum` of its `.crate` file.
+    ///
+    /// ```
+    /// use crates_index::{Dependency, DependencyKind, Version};
+    ///
+    /// let version = Version::builder("foo", "0.1.0", [0; 32])
+    ///     .dependency(Dependency::builder("serde", "^1").optional(true).build()?)
+    ///     .dependency(Dependency::builder("tempfile", "^3").kind(DependencyKind::Dev).build()?)
+    ///     .feature("serde", ["dep:serde"])
+    ///     .rust_version("1.70")
+    ///     .build()?;
+    /// assert_eq!(version.dependencies().len(), 2);
+    /// # Ok::<_, crates_index::Error>(())
+    /// ```
+    pub fn builder(name: impl Into<String>, vers: impl Into<String>, cksum: [u8; 32]) -> VersionBuilder {
+        VersionBuilder {
+            name: name.into(),
+            vers: vers.into(),
+            cksum,
+            deps: Vec::new(),
+            features: HashMap::new(),
+            links: None,
+            rust_version: None,
+            yanked: false,
+        }
+    }
+
     /// Name of the crate
     #[inline]
     #[must_use]
@@ -122,30 +152,54 @@ impl Version {
     /// Where to find crate tarball
     #[must_use]
     pub fn download_url(&self, index: &IndexConfig) -> Option<String> {
-        index.download_url(&self.name, &self.vers)
+        index.download_url_for(self)
+    }
+
+    /// Serializes this version as a line of an index file, without the trailing newline, exactly as crates.io writes it.
+    ///
+    /// Features using the syntax for namespaced or weak dependency features are written separately as `features2`,
+    /// as crates.io does so older versions of cargo can still read the line.
+    #[must_use]
+    pub fn to_index_line(&self) -> String {
+        serde_json::to_string(&IndexLine::new(self)).expect("serializing a version never fails")
     }
 }
 
 /// A single dependency of a specific crate version
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct Dependency {
-    name: SmolStr,
-    req: SmolStr,
+    pub(crate) name: SmolStr,
+    pub(crate) req: SmolStr,
     /// Double indirection to remove size from this struct, since the features are rarely set
-    features: Box<Box<[String]>>,
+    pub(crate) features: Box<Box<[String]>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    package: Option<Box<SmolStr>>,
+    pub(crate) package: Option<Box<SmolStr>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    kind: Option<DependencyKind>,
+    pub(crate) kind: Option<DependencyKind>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    registry: Option<SmolStr>,
+    pub(crate) registry: Option<SmolStr>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    target: Option<Box<SmolStr>>,
-    optional: bool,
-    default_features: bool,
+    pub(crate) target: Option<Box<SmolStr>>,
+    pub(crate) optional: bool,
+    pub(crate) default_features: bool,
 }
 
 impl Dependency {
+    /// Start building a normal dependency on the crate `name` with the version requirement `req`, like `^1.0`.
+    pub fn builder(name: impl Into<String>, req: impl Into<String>) -> DependencyBuilder {
+        DependencyBuilder {
+            name: name.into(),
+            req: req.into(),
+            features: Vec::new(),
+            package: None,
+            kind: DependencyKind::Normal,
+            registry: None,
+            target: None,
+            optional: false,
+            default_features: true,
+        }
+    }
+
     /// Dependency's arbitrary nickname (it may be an alias). Use [`Dependency::crate_name`] for actual crate name.
     #[inline]
     #[must_use]
@@ -251,7 +305,7 @@ pub enum DependencyKind {
 }
 
 /// A whole crate with all its versions
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub struct Crate {
     versions: Box<[Version]>,
 }
@@ -296,24 +350,42 @@ impl Crate {
     /// 4. The cache entry is malformed somehow
     #[inline(never)]
     pub(crate) fn from_cache_slice(bytes: &[u8], index_version: Option<&str>) -> io::Result<Self> {
-        const CURRENT_CACHE_VERSION: u8 = 3;
-        const CURRENT_INDEX_FORMAT_VERSION: u32 = 2;
+        let (update, iter) = Self::split_cache_slice(bytes)?;
+        if let Some(index_version) = index_version {
+            if update != index_version.as_bytes() {
+                return Err(io::Error::other(format!(
+                    "cache out of date: current index ({index_version}) != cache ({})",
+                    String::from_utf8_lossy(update)
+                )));
+            }
+        }
+
+        Self::from_version_entries_iter(iter)
+    }
 
-        // See src/cargo/sources/registry/index.rs
-        let (first_byte, mut rest) = bytes.split_first().ok_or(io::ErrorKind::UnexpectedEof)?;
+    /// Like [`Self::from_cache_slice()`], but returns the version of the cache entry along with the crate.
+    pub(crate) fn from_cache_slice_with_version(bytes: &[u8]) -> io::Result<(Self, String)> {
+        let (update, iter) = Self::split_cache_slice(bytes)?;
+        let update = std::str::from_utf8(update).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok((Self::from_version_entries_iter(iter)?, update.to_owned()))
+    }
 
-        match *first_byte {
+    /// Validate the header of a cache entry and return its version along with an iterator over its version entries.
+    fn split_cache_slice(bytes: &[u8]) -> io::Result<(&[u8], impl Iterator<Item = &[u8]> + '_)> {
+        let header = cache::Header::parse(bytes)?;
+        match header.cache_version {
             // This is the current 1.54.0 - 1.70.0+ version of cache entries
-            CURRENT_CACHE_VERSION => {
-                let index_v_bytes = rest.get(..4).ok_or(io::ErrorKind::UnexpectedEof)?;
-                let index_v = u32::from_le_bytes(index_v_bytes.try_into().unwrap());
-                if index_v != CURRENT_INDEX_FORMAT_VERSION {
+            cache::CURRENT_CACHE_VERSION => {
+                let index_v = header.index_format_version.unwrap_or_default();
+                if index_v != cache::CURRENT_INDEX_FORMAT_VERSION {
                     return Err(io::Error::new(
                         io::ErrorKind::Unsupported,
-                        format!("wrong index format version: {index_v} (expected {CURRENT_INDEX_FORMAT_VERSION}))"),
+                        format!(
+                            "wrong index format version: {index_v} (expected {}))",
+                            cache::CURRENT_INDEX_FORMAT_VERSION
+                        ),
                     ));
                 }
-                rest = &rest[4..];
             }
             // This is only to support ancient <1.52.0 versions of cargo https://github.com/rust-lang/cargo/pull/9161
             1 => {}
@@ -334,18 +406,7 @@ impl Crate {
             }
         }
 
-        let mut iter = crate::split(rest, 0);
-        let update = iter.next().ok_or(io::ErrorKind::UnexpectedEof)?;
-        if let Some(index_version) = index_version {
-            if update != index_version.as_bytes() {
-                return Err(io::Error::other(format!(
-                    "cache out of date: current index ({index_version}) != cache ({})",
-                    String::from_utf8_lossy(update)
-                )));
-            }
-        }
-
-        Self::from_version_entries_iter(iter)
+        Ok((header.version, crate::split(header.entries, 0)))
     }
 
     pub(crate) fn from_version_entries_iter<'a, I: Iterator<Item = &'a [u8]> + 'a>(mut iter: I) -> io::Result<Crate> {
@@ -369,15 +430,58 @@ impl Crate {
         })
     }
 
+    /// Create a crate from all its `versions` in the order they were published, like for fixtures
+    /// or to generate an index.
+    ///
+    /// All versions must have the same name, and like on crates.io, versions which only differ in their build
+    /// metadata are duplicates. Use [`Crate::to_index_lines()`] to get its index file.
+    pub fn from_versions(versions: impl IntoIterator<Item = Version>) -> Result<Crate, Error> {
+        let versions: Box<[Version]> = versions.into_iter().collect();
+        let Some(first) = versions.first() else {
+            return Err(invalid_entry("a crate needs at least one version".into()));
+        };
+        for (i, version) in versions.iter().enumerate() {
+            if version.name() != first.name() {
+                return Err(invalid_entry(format!(
+                    "the crate '{}' has a version named '{}'",
+                    first.name(),
+                    version.name()
+                )));
+            }
+            if let Some(previous) = versions[..i]
+                .iter()
+                .find(|previous| is_same_version(previous.version(), version.version()))
+            {
+                return Err(invalid_entry(format!(
+                    "the crate '{}' has version {} twice, as {}",
+                    first.name(),
+                    version.version(),
+                    previous.version()
+                )));
+            }
+        }
+        Ok(Crate { versions })
+    }
+
+    /// Serializes all versions in the format of index files, with one line per version, see [`Version::to_index_line()`].
+    ///
+    /// Parsing the result with [`Crate::from_slice()`] produces a crate equal to this one.
+    #[must_use]
+    pub fn to_index_lines(&self) -> Vec<u8> {
+        let mut lines = Vec::new();
+        for version in self.versions() {
+            lines.extend_from_slice(version.to_index_line().as_bytes());
+            lines.push(b'\n');
+        }
+        lines
+    }
+
     /// Writes a cache entry to disk in the same format as cargo
     #[cfg(feature = "sparse")]
     pub(crate) fn write_cache_entry(&self, path: &Path, version: &str) -> io::Result<()> {
-        const CURRENT_CACHE_VERSION: u8 = 3;
-        const CURRENT_INDEX_FORMAT_VERSION: u32 = 2;
-
         let mut v = Vec::new();
-        v.push(CURRENT_CACHE_VERSION);
-        v.extend_from_slice(&CURRENT_INDEX_FORMAT_VERSION.to_le_bytes());
+        v.push(cache::CURRENT_CACHE_VERSION);
+        v.extend_from_slice(&cache::CURRENT_INDEX_FORMAT_VERSION.to_le_bytes());
         v.extend_from_slice(version.as_bytes());
         v.push(0);
 
@@ -388,7 +492,7 @@ impl Crate {
             v.push(0);
         }
 
-        std::fs::write(path, v)
+        cache::write_atomic(path, &v)
     }
 
     /// All versions of this crate sorted chronologically by date originally published
@@ -500,3 +604,285 @@ impl Crate {
         Self::from_slice_with_context(bytes, &mut dedupe)
     }
 }
+
+/// Builds a [`Version`], see [`Version::builder()`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct VersionBuilder {
+    name: String,
+    vers: String,
+    cksum: [u8; 32],
+    deps: Vec<Dependency>,
+    features: HashMap<String, Vec<String>>,
+    links: Option<String>,
+    rust_version: Option<String>,
+    yanked: bool,
+}
+
+impl VersionBuilder {
+    /// Add `dep` to the dependencies.
+    pub fn dependency(mut self, dep: Dependency) -> Self {
+        self.deps.push(dep);
+        self
+    }
+
+    /// Add all `deps` to the dependencies.
+    pub fn dependencies(mut self, deps: impl IntoIterator<Item = Dependency>) -> Self {
+        self.deps.extend(deps);
+        self
+    }
+
+    /// Add the feature `name` which enables `values`, which can be other features, optional dependencies
+    /// or use the syntax for namespaced and weak dependency features, like `dep:serde` or `serde?/std`.
+    pub fn feature(mut self, name: impl Into<String>, values: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.features
+            .insert(name.into(), values.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the name of the native library the crate links to, see [`Version::links()`].
+    pub fn links(mut self, links: impl Into<String>) -> Self {
+        self.links = Some(links.into());
+        self
+    }
+
+    /// Set the minimal supported Rust version, like `1.70`, see [`Version::rust_version()`].
+    pub fn rust_version(mut self, rust_version: impl Into<String>) -> Self {
+        self.rust_version = Some(rust_version.into());
+        self
+    }
+
+    /// Set if the version is yanked.
+    pub fn yanked(mut self, yes: bool) -> Self {
+        self.yanked = yes;
+        self
+    }
+
+    /// Validate the name, version and Rust version, and create the version.
+    pub fn build(self) -> Result<Version, Error> {
+        validate_crate_name(&self.name)?;
+        SemverVersion::parse(&self.vers).map_err(|err| {
+            invalid_entry(format!(
+                "the version '{}' of '{}' is invalid: {err}",
+                self.vers, self.name
+            ))
+        })?;
+        if let Some(rust_version) = &self.rust_version {
+            let components: Vec<_> = rust_version.split('.').collect();
+            if !(2..=3).contains(&components.len())
+                || components
+                    .iter()
+                    .any(|c| c.is_empty() || !c.bytes().all(|b| b.is_ascii_digit()))
+            {
+                return Err(invalid_entry(format!(
+                    "the Rust version '{rust_version}' of '{}' is invalid",
+                    self.name
+                )));
+            }
+        }
+        Ok(Version {
+            name: self.name.into(),
+            vers: self.vers.into(),
+            deps: self.deps.into(),
+            features: Arc::new(self.features),
+            features2: None,
+            links: self.links.map(|links| Box::new(links.into())),
+            rust_version: self.rust_version.map(Into::into),
+            cksum: self.cksum,
+            yanked: self.yanked,
+        })
+    }
+}
+
+/// Builds a [`Dependency`], see [`Dependency::builder()`].
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct DependencyBuilder {
+    name: String,
+    req: String,
+    features: Vec<String>,
+    package: Option<String>,
+    kind: DependencyKind,
+    registry: Option<String>,
+    target: Option<String>,
+    optional: bool,
+    default_features: bool,
+}
+
+impl DependencyBuilder {
+    /// Enable `features` of the dependency.
+    pub fn features(mut self, features: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.features.extend(features.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set if the dependency is optional.
+    pub fn optional(mut self, yes: bool) -> Self {
+        self.optional = yes;
+        self
+    }
+
+    /// Set if the default features of the dependency are enabled, which is the default.
+    pub fn default_features(mut self, yes: bool) -> Self {
+        self.default_features = yes;
+        self
+    }
+
+    /// Only use the dependency when compiling for `target`, like `cfg(unix)`.
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Set the section in which the dependency is defined, which is [`DependencyKind::Normal`] by default.
+    pub fn kind(mut self, kind: DependencyKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Set the URL of the index of the registry the dependency comes from, if it's not the one of the crate.
+    pub fn registry(mut self, registry: impl Into<String>) -> Self {
+        self.registry = Some(registry.into());
+        self
+    }
+
+    /// Depend on the crate `package` while using the name of the dependency in the code, see [`Dependency::package()`].
+    pub fn package(mut self, package: impl Into<String>) -> Self {
+        self.package = Some(package.into());
+        self
+    }
+
+    /// Validate the names and the version requirement, and create the dependency.
+    pub fn build(self) -> Result<Dependency, Error> {
+        validate_crate_name(&self.name)?;
+        if let Some(package) = &self.package {
+            validate_crate_name(package)?;
+        }
+        semver::VersionReq::parse(&self.req).map_err(|err| {
+            invalid_entry(format!(
+                "the version requirement '{}' of the dependency '{}' is invalid: {err}",
+                self.req, self.name
+            ))
+        })?;
+        Ok(Dependency {
+            name: self.name.into(),
+            req: self.req.into(),
+            features: Box::new(self.features.into_boxed_slice()),
+            package: self.package.map(|package| Box::new(package.into())),
+            kind: Some(self.kind),
+            registry: self.registry.map(Into::into),
+            target: self.target.map(|target| Box::new(target.into())),
+            optional: self.optional,
+            default_features: self.default_features,
+        })
+    }
+}
+
+fn invalid_entry(reason: String) -> Error {
+    Error::InvalidEntry { reason }
+}
+
+/// Crate names are ASCII letters, digits, `-` and `_`, and start with a letter.
+fn validate_crate_name(name: &str) -> Result<(), Error> {
+    let mut bytes = name.bytes();
+    let is_valid = bytes.next().is_some_and(|b| b.is_ascii_alphabetic())
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if is_valid {
+        Ok(())
+    } else {
+        Err(invalid_entry(format!("the crate name '{name}' is invalid")))
+    }
+}
+
+/// Compare versions like crates.io, ignoring build metadata.
+pub(crate) fn is_same_version(a: &str, b: &str) -> bool {
+    match (SemverVersion::parse(a), SemverVersion::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp_precedence(&b).is_eq(),
+        _ => a == b,
+    }
+}
+
+/// A version in the format in which crates.io writes it to the index, see [`Version::to_index_line()`].
+#[derive(Serialize)]
+struct IndexLine<'a> {
+    name: &'a str,
+    vers: &'a str,
+    deps: Vec<IndexDependency<'a>>,
+    cksum: String,
+    features: BTreeMap<&'a str, Vec<&'a str>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    features2: BTreeMap<&'a str, Vec<&'a str>>,
+    yanked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    links: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rust_version: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    v: Option<u32>,
+}
+
+impl<'a> IndexLine<'a> {
+    fn new(version: &'a Version) -> Self {
+        // Parsing merges `features2` into `features`, so split them again like crates.io does.
+        let mut all_features: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (name, values) in version
+            .features
+            .iter()
+            .chain(version.features2.iter().flat_map(|f| f.iter()))
+        {
+            all_features
+                .entry(name)
+                .or_default()
+                .extend(values.iter().map(String::as_str));
+        }
+        let (features2, features): (BTreeMap<_, _>, BTreeMap<_, _>) =
+            all_features.into_iter().partition(|(_, values)| {
+                values
+                    .iter()
+                    .any(|value| value.starts_with("dep:") || value.contains("?/"))
+            });
+        Self {
+            name: version.name(),
+            vers: version.version(),
+            deps: version
+                .dependencies()
+                .iter()
+                .map(|dep| IndexDependency {
+                    name: dep.name(),
+                    req: dep.requirement(),
+                    features: dep.features(),
+                    optional: dep.is_optional(),
+                    default_features: dep.has_default_features(),
+                    target: dep.target(),
+                    kind: dep.kind,
+                    registry: dep.registry(),
+                    package: dep.package(),
+                })
+                .collect(),
+            cksum: hex::encode(version.checksum()),
+            v: (!features2.is_empty()).then_some(2),
+            features,
+            features2,
+            yanked: version.is_yanked(),
+            links: version.links(),
+            rust_version: version.rust_version(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct IndexDependency<'a> {
+    name: &'a str,
+    req: &'a str,
+    features: &'a [String],
+    optional: bool,
+    default_features: bool,
+    target: Option<&'a str>,
+    /// crates.io always writes it, so it's only missing if it was missing in the line the dependency was parsed from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<DependencyKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registry: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    package: Option<&'a str>,
+}
@@ -1,5 +1,10 @@
+#![allow(clippy::result_large_err)]
+
 mod git;
+mod local;
 mod names;
+#[cfg(feature = "package")]
+mod package;
 mod sparse_index;
 mod error {
     #[test]
@@ -9,7 +14,129 @@ mod error {
     }
 }
 
-use crates_index::{Crate, Dependency, Version};
+mod lock {
+    use crates_index::lock::{LockMode, PackageCacheLock};
+    use std::fs::File;
+
+    fn is_locked_by_others(path: &std::path::Path) -> bool {
+        let Ok(file) = File::open(path) else {
+            return false;
+        };
+        match fs4::FileExt::try_lock(&file) {
+            Ok(()) => {
+                fs4::FileExt::unlock(&file).unwrap();
+                false
+            }
+            Err(fs4::TryLockError::WouldBlock) => true,
+            Err(fs4::TryLockError::Error(err)) => panic!("{err}"),
+        }
+    }
+
+    #[test]
+    fn exclusive_locks_are_reentrant_within_a_thread() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let lock_file = cargo_home.path().join(".package-cache");
+
+        let first = PackageCacheLock::acquire(cargo_home.path(), LockMode::DownloadExclusive).unwrap();
+        assert!(is_locked_by_others(&lock_file));
+        let second = PackageCacheLock::acquire(cargo_home.path(), LockMode::MutateExclusive).unwrap();
+        assert!(is_locked_by_others(&cargo_home.path().join(".package-cache-mutate")));
+
+        drop(first);
+        assert!(is_locked_by_others(&lock_file), "the second lock still holds it");
+        drop(second);
+        assert!(!is_locked_by_others(&lock_file), "all locks were released");
+    }
+
+    #[test]
+    fn exclusive_locks_exclude_other_threads() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let first = PackageCacheLock::acquire(cargo_home.path(), LockMode::DownloadExclusive).unwrap();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let lock = PackageCacheLock::acquire(cargo_home.path(), LockMode::DownloadExclusive).unwrap();
+                tx.send(()).unwrap();
+                drop(lock);
+            });
+            assert!(
+                rx.recv_timeout(std::time::Duration::from_millis(200)).is_err(),
+                "the other thread waits for the first lock"
+            );
+            drop(first);
+            rx.recv_timeout(std::time::Duration::from_secs(10))
+                .expect("the other thread gets the lock once it's released");
+        });
+        assert!(!is_locked_by_others(&cargo_home.path().join(".package-cache")));
+    }
+
+    #[test]
+    fn waiting_threads_do_not_prevent_releasing_locks() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let shared = PackageCacheLock::acquire(cargo_home.path(), LockMode::Shared).unwrap();
+        let exclusive = PackageCacheLock::acquire(cargo_home.path(), LockMode::DownloadExclusive).unwrap();
+
+        std::thread::scope(|s| {
+            let waiting = s.spawn(|| PackageCacheLock::acquire(cargo_home.path(), LockMode::DownloadExclusive));
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            let releasing = s.spawn(move || drop(shared));
+            releasing.join().unwrap();
+            drop(exclusive);
+            waiting.join().unwrap().unwrap();
+        });
+        assert!(!is_locked_by_others(&cargo_home.path().join(".package-cache-mutate")));
+    }
+
+    #[test]
+    fn shared_lock_does_not_block_downloads() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let _shared = PackageCacheLock::acquire(cargo_home.path(), LockMode::Shared).unwrap();
+        assert!(!is_locked_by_others(&cargo_home.path().join(".package-cache")));
+    }
+
+    #[test]
+    fn index_locks_need_cargo_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(
+            PackageCacheLock::for_index(&dir.path().join("index"), LockMode::DownloadExclusive)
+                .unwrap()
+                .is_none()
+        );
+
+        let index_path = dir.path().join("registry/index/example.com-0123456789abcdef");
+        let lock = PackageCacheLock::for_index(&index_path, LockMode::DownloadExclusive).unwrap();
+        assert!(lock.is_some());
+        assert!(is_locked_by_others(&dir.path().join(".package-cache")));
+    }
+
+    #[test]
+    #[cfg(feature = "sparse")]
+    fn sparse_cache_writes_are_atomic_and_locked() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let index = crates_index::SparseIndex::with_path(cargo_home.path(), crates_index::sparse::URL).unwrap();
+        let response = http::Response::builder()
+            .status(200)
+            .header(http::header::ETAG, "W/\"5f15de4a723e10b3f9eaf048d693cccc\"")
+            .body(include_bytes!("fixtures/autocfg.txt").to_vec())
+            .unwrap();
+        index.parse_cache_response("autocfg", response, true).unwrap();
+
+        assert!(
+            cargo_home.path().join(".package-cache").is_file(),
+            "the lock of cargo was taken"
+        );
+        let cache_dir = index.path().join(".cache/au/to");
+        let entries: Vec<_> = std::fs::read_dir(cache_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, ["autocfg"], "no temporary files are left behind");
+        assert_eq!(index.crate_from_cache("autocfg").unwrap().versions().len(), 13);
+    }
+}
+
+use crates_index::{Crate, Dependency, IndexConfig, Version};
 
 #[test]
 fn sizes() {
@@ -44,3 +171,158 @@ fn rust_version() {
     let c = Crate::from_slice(br#"{"vers":"1.0.0", "name":"test", "deps":[], "features":{},"features2":{}, "cksum":"1234567890123456789012345678901234567890123456789012345678901234", "rust_version":"1.64.0"}"#).unwrap();
     assert_eq!(c.most_recent_version().rust_version(), Some("1.64.0"));
 }
+
+#[test]
+fn index_config_download_urls() {
+    let c = Crate::from_slice(br#"{"vers":"1.0.0", "name":"Test", "deps":[], "features":{}, "cksum":"1234567890123456789012345678901234567890123456789012345678901234"}"#).unwrap();
+    let version = c.most_recent_version();
+
+    let config: IndexConfig = serde_json::from_str(r#"{"dl":"https://example.com/api/v1/crates"}"#).unwrap();
+    assert_eq!(
+        version.download_url(&config).unwrap(),
+        "https://example.com/api/v1/crates/Test/1.0.0/download"
+    );
+
+    let config: IndexConfig =
+        serde_json::from_str(r#"{"dl":"https://example.com/{lowerprefix}/{crate}/{version}/{sha256-checksum}"}"#)
+            .unwrap();
+    assert_eq!(
+        version.download_url(&config).unwrap(),
+        "https://example.com/te/st/Test/1.0.0/1234567890123456789012345678901234567890123456789012345678901234"
+    );
+    assert_eq!(
+        config.download_url("Test", "1.0.0"),
+        None,
+        "the checksum isn't known without the version"
+    );
+}
+
+#[test]
+fn index_config_roundtrip() {
+    let json = r#"{"dl":"https://example.com/dl","api":"https://example.com","auth-required":true,"custom":{"x":1}}"#;
+    let config: IndexConfig = serde_json::from_str(json).unwrap();
+    assert!(config.auth_required);
+    assert_eq!(config.extra["custom"]["x"], 1);
+    assert_eq!(serde_json::to_string(&config).unwrap(), json);
+
+    let config: IndexConfig = serde_json::from_str(r#"{"dl":"https://example.com/dl"}"#).unwrap();
+    assert!(!config.auth_required && config.api.is_none() && config.extra.is_empty());
+    assert_eq!(
+        serde_json::to_value(&config).unwrap(),
+        serde_json::to_value(IndexConfig::new("https://example.com/dl")).unwrap()
+    );
+    assert_eq!(
+        serde_json::to_string(&config).unwrap(),
+        r#"{"dl":"https://example.com/dl"}"#,
+        "defaults are omitted"
+    );
+}
+
+#[test]
+fn builders() {
+    use crates_index::DependencyKind;
+
+    let first = Version::builder("foo", "0.1.0", [1; 32])
+        .dependency(
+            Dependency::builder("serde_lib", "^1.0.100")
+                .package("serde")
+                .features(["derive"])
+                .optional(true)
+                .build()
+                .unwrap(),
+        )
+        .dependency(
+            Dependency::builder("libc", "0.2")
+                .target("cfg(unix)")
+                .default_features(false)
+                .registry("https://example.com/index")
+                .build()
+                .unwrap(),
+        )
+        .feature("default", ["std"])
+        .feature("std", Vec::<String>::new())
+        .feature("serde", ["dep:serde_lib", "serde_lib?/std"])
+        .links("foo")
+        .rust_version("1.70")
+        .build()
+        .unwrap();
+    assert_eq!(first.name(), "foo");
+    assert_eq!(first.checksum(), &[1; 32]);
+    assert_eq!(first.links(), Some("foo"));
+    assert_eq!(first.rust_version(), Some("1.70"));
+    let serde = &first.dependencies()[0];
+    assert_eq!(serde.crate_name(), "serde");
+    assert_eq!(serde.kind(), DependencyKind::Normal);
+    assert!(serde.is_optional());
+    let libc = &first.dependencies()[1];
+    assert_eq!(libc.target(), Some("cfg(unix)"));
+    assert!(!libc.has_default_features());
+
+    let second = Version::builder("foo", "0.2.0-rc.1", [2; 32])
+        .dependencies([Dependency::builder("tempfile", "3")
+            .kind(DependencyKind::Dev)
+            .build()
+            .unwrap()])
+        .yanked(true)
+        .build()
+        .unwrap();
+    let krate = Crate::from_versions([first, second]).unwrap();
+    assert_eq!(krate.name(), "foo");
+    assert_eq!(krate.most_recent_version().version(), "0.2.0-rc.1");
+
+    let lines = krate.to_index_lines();
+    assert_eq!(lines.iter().filter(|b| **b == b'\n').count(), 2);
+    assert_eq!(Crate::from_slice(&lines).unwrap(), krate, "index lines round-trip");
+}
+
+#[test]
+fn parsed_index_lines_round_trip() {
+    let c = Crate::from_slice(br#"{"vers":"1.0.0", "name":"test", "deps":[{"name":"a","req":"^1","features":[],"optional":false,"default_features":true}], "features":{"a":["one"]},"features2":{"b":["dep:a"]}, "cksum":"1234567890123456789012345678901234567890123456789012345678901234"}
+            {"vers":"1.0.1", "name":"test", "deps":[{"name":"a","req":"^1","features":[],"optional":true,"default_features":true,"target":null,"kind":"dev","package":"b"}], "features":{}, "cksum":"1234567890123456789012345678901234567890123456789012345678901234", "yanked":true, "links":"test", "rust_version":"1.64.0"}"#).unwrap();
+    assert_eq!(Crate::from_slice(&c.to_index_lines()).unwrap(), c);
+}
+
+#[test]
+fn index_lines_are_written_like_crates_io() {
+    for fixture in [
+        &include_bytes!("fixtures/crates-index.txt")[..],
+        &include_bytes!("fixtures/autocfg.txt")[..],
+    ] {
+        let c = Crate::from_slice(fixture).unwrap();
+        assert_eq!(
+            String::from_utf8(c.to_index_lines()).unwrap(),
+            std::str::from_utf8(fixture).unwrap(),
+            "index files of crates.io are reproduced byte for byte"
+        );
+    }
+}
+
+#[test]
+fn invalid_builders() {
+    fn is_invalid_entry<T: std::fmt::Debug>(result: Result<T, crates_index::Error>) -> bool {
+        matches!(result, Err(crates_index::Error::InvalidEntry { .. }))
+    }
+
+    assert!(is_invalid_entry(Version::builder("", "0.1.0", [0; 32]).build()));
+    assert!(is_invalid_entry(Version::builder("1foo", "0.1.0", [0; 32]).build()));
+    assert!(is_invalid_entry(Version::builder("foo bar", "0.1.0", [0; 32]).build()));
+    assert!(is_invalid_entry(Version::builder("foo", "0.1", [0; 32]).build()));
+    assert!(is_invalid_entry(
+        Version::builder("foo", "0.1.0", [0; 32]).rust_version("1.x").build()
+    ));
+    assert!(is_invalid_entry(
+        Dependency::builder("bar", "not a requirement").build()
+    ));
+    assert!(is_invalid_entry(Dependency::builder("bar", "1").package("b@r").build()));
+
+    let version = |vers: &str| Version::builder("foo", vers, [0; 32]).build().unwrap();
+    assert!(is_invalid_entry(Crate::from_versions([])));
+    assert!(is_invalid_entry(Crate::from_versions([
+        version("1.0.0+a"),
+        version("1.0.0+b")
+    ])));
+    assert!(is_invalid_entry(Crate::from_versions([
+        version("1.0.0"),
+        Version::builder("Foo", "2.0.0", [0; 32]).build().unwrap()
+    ])));
+}
@@ -198,6 +198,27 @@ pub(crate) mod with_https {
         assert!(index.crate_("無").is_none());
     }
 
+    #[test]
+    #[cfg(feature = "sparse")]
+    fn serves_the_current_commit() {
+        use crates_index::sparse::SparseServer;
+        use http::StatusCode;
+
+        let index = shared_index();
+        let expected = index.crate_("serde").unwrap();
+        let server = SparseServer::new(index);
+        let get = |path: &str| server.handle(&http::Request::get(path).body(()).unwrap()).unwrap();
+
+        let response = get("/se/rd/serde");
+        assert_eq!(response.status(), StatusCode::OK);
+        let krate = crates_index::Crate::from_slice(response.body()).unwrap();
+        assert_eq!(krate.versions().len(), expected.versions().len());
+
+        let config: crates_index::IndexConfig = serde_json::from_slice(get("/config.json").body()).unwrap();
+        assert_eq!(config.dl, server.source().index_config().unwrap().dl);
+        assert_eq!(get("/no/ne/nonexisting-crate").status(), StatusCode::NOT_FOUND);
+    }
+
     pub(crate) fn shared_index() -> GitIndex {
         static LOCK: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
         let _guard = LOCK.lock();
@@ -215,3 +236,138 @@ pub(crate) mod with_https {
         }
     }
 }
+
+#[cfg(feature = "git")]
+mod writer {
+    use crates_index::git::IndexWriter;
+    use crates_index::{Error, GitIndex, IndexConfig, Version};
+
+    fn version(name: &str, vers: &str) -> Version {
+        serde_json::from_str(&format!(
+            r#"{{"name":"{name}","vers":"{vers}","deps":[],"cksum":"{}","features":{{}},"yanked":false}}"#,
+            "0".repeat(64)
+        ))
+        .unwrap()
+    }
+
+    fn config() -> IndexConfig {
+        serde_json::from_str(r#"{"dl":"https://example.com/api/v1/crates","api":"https://example.com"}"#).unwrap()
+    }
+
+    fn head_message(index: &IndexWriter) -> String {
+        let repo = gix::open(index.path()).unwrap();
+        let commit = repo.head_commit().unwrap();
+        commit.message_raw().unwrap().to_string()
+    }
+
+    #[test]
+    fn publish_yank_and_unyank() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let index = IndexWriter::init(tmp_dir.path().join("index.git"))
+            .unwrap()
+            .signature("registry", "registry@example.com");
+        assert_eq!(index.commit().unwrap(), None);
+        assert!(index.index_config().unwrap().is_none());
+        assert!(index.crate_("foo").unwrap().is_none());
+
+        assert!(index.set_config(&config()).unwrap());
+        assert!(
+            !index.set_config(&config()).unwrap(),
+            "unchanged configuration isn't committed"
+        );
+        assert_eq!(index.index_config().unwrap().unwrap().dl, config().dl);
+        assert_eq!(head_message(&index), "Updating config.json");
+
+        index.publish(&version("foo", "0.1.0")).unwrap();
+        assert_eq!(head_message(&index), "Updating crate `foo#0.1.0`");
+        index.publish(&version("foo", "0.2.0")).unwrap();
+        let krate = index.crate_("FOO").unwrap().unwrap();
+        assert_eq!(krate.versions().len(), 2);
+        assert_eq!(krate.highest_version().version(), "0.2.0");
+
+        let commit = index.commit().unwrap();
+        assert!(index.yank("foo", "0.1.0").unwrap());
+        assert_ne!(index.commit().unwrap(), commit);
+        assert_eq!(head_message(&index), "Yanking crate `foo#0.1.0`");
+        assert!(!index.yank("foo", "0.1.0").unwrap(), "yanking twice changes nothing");
+        let krate = index.crate_("foo").unwrap().unwrap();
+        assert!(krate.versions()[0].is_yanked());
+        assert!(!krate.versions()[1].is_yanked());
+
+        assert!(index.unyank("foo", "0.1.0").unwrap());
+        assert_eq!(head_message(&index), "Unyanking crate `foo#0.1.0`");
+        assert!(!index.crate_("foo").unwrap().unwrap().versions()[0].is_yanked());
+    }
+
+    #[test]
+    fn rejected_changes() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let index = IndexWriter::init(tmp_dir.path().join("index.git")).unwrap();
+        index.publish(&version("foo", "1.0.0+build")).unwrap();
+
+        let err = index.publish(&version("foo", "1.0.0+other-build")).unwrap_err();
+        assert!(
+            matches!(&err, Error::VersionExists { crate_name, version } if crate_name == "foo" && version == "1.0.0+build"),
+            "{err:?}"
+        );
+        let err = index.publish(&version("Foo", "2.0.0")).unwrap_err();
+        assert!(matches!(err, Error::InvalidPackage { .. }), "{err:?}");
+        let err = index.publish(&version("", "2.0.0")).unwrap_err();
+        assert!(matches!(err, Error::InvalidPackage { .. }), "{err:?}");
+
+        for (name, vers) in [("foo", "2.0.0"), ("bar", "1.0.0")] {
+            let err = index.yank(name, vers).unwrap_err();
+            assert!(matches!(err, Error::VersionNotFound { .. }), "{err:?}");
+        }
+        assert_eq!(index.crate_("foo").unwrap().unwrap().versions().len(), 1);
+    }
+
+    #[test]
+    fn cloned_by_git_index() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let repo_path = tmp_dir.path().join("index.git");
+        let index = IndexWriter::init(&repo_path).unwrap();
+        index.set_config(&config()).unwrap();
+        index.publish(&version("foo", "0.1.0")).unwrap();
+
+        let url = repo_path.to_str().unwrap();
+        let mut clone = GitIndex::with_path(tmp_dir.path().join("clone"), url).unwrap();
+        assert_eq!(clone.index_config().unwrap().dl, config().dl);
+        assert_eq!(clone.crate_("foo").unwrap().versions().len(), 1);
+
+        let writer = IndexWriter::open(&repo_path).unwrap();
+        writer.publish(&version("bar", "1.0.0")).unwrap();
+        writer.yank("foo", "0.1.0").unwrap();
+        clone.update().unwrap();
+        assert_eq!(clone.commit(), &writer.commit().unwrap().unwrap());
+        assert!(clone.crate_("foo").unwrap().versions()[0].is_yanked());
+        let changes: Vec<_> = clone
+            .changes()
+            .unwrap()
+            .map(|change| change.unwrap().crate_name().to_owned())
+            .collect();
+        assert_eq!(changes, ["foo", "bar", "foo"]);
+    }
+
+    #[test]
+    fn as_index() {
+        use crates_index::{BoxedIndex, Index};
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let repo_path = tmp_dir.path().join("index.git");
+        let writer = IndexWriter::init(&repo_path).unwrap();
+        writer.publish(&version("foo", "0.1.0")).unwrap();
+
+        let url = repo_path.to_str().unwrap();
+        let index: BoxedIndex = GitIndex::with_path(tmp_dir.path().join("clone"), url).unwrap().boxed();
+        assert_eq!(index.url(), url);
+        assert!(index.index_config().unwrap().is_none(), "there is no config.json yet");
+        assert_eq!(index.crate_("Foo").unwrap().unwrap().name(), "foo");
+        assert!(index.crate_("bar").unwrap().is_none());
+        assert_eq!(index.crates().unwrap().count(), 1);
+        assert!(
+            index.last_updated("bar").unwrap().is_some(),
+            "the time of the commit is known for all crates"
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use crates_index::{BoxedIndex, Crate, DependencyKind, DirectoryIndex, Index, LocalRegistryIndex, Version};
+use std::path::Path;
+
+fn version(name: &str, vers: &str) -> Version {
+    Version::builder(name, vers, [7; 32]).build().unwrap()
+}
+
+mod local_registry {
+    use super::*;
+
+    fn write_crate(root: &Path, rel_path: &str, versions: &[Version]) {
+        let path = root.join("index").join(rel_path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let krate = Crate::from_versions(versions.iter().cloned()).unwrap();
+        std::fs::write(path, krate.to_index_lines()).unwrap();
+    }
+
+    #[test]
+    fn crates_and_crate_files() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        write_crate(
+            tmp_dir.path(),
+            "se/rd/serde",
+            &[version("serde", "1.0.0"), version("serde", "1.0.1")],
+        );
+        write_crate(tmp_dir.path(), "3/l/log", &[version("log", "0.4.0")]);
+        std::fs::write(tmp_dir.path().join("index/config.json"), "{}").unwrap();
+        std::fs::write(tmp_dir.path().join("index/3/l/not-a-crate"), "garbage").unwrap();
+
+        let index = LocalRegistryIndex::new(tmp_dir.path());
+        assert_eq!(index.path(), tmp_dir.path());
+        let serde = index.crate_("Serde").unwrap().expect("lookups ignore case");
+        assert_eq!(serde.versions().len(), 2);
+        assert_eq!(
+            index.crate_file_path(serde.highest_version()),
+            tmp_dir.path().join("serde-1.0.1.crate")
+        );
+        assert!(index.crate_("missing").unwrap().is_none());
+        assert!(index.crate_("").unwrap().is_none());
+
+        let mut names: Vec<_> = index.crates().map(|krate| krate.name().to_owned()).collect();
+        names.sort();
+        assert_eq!(names, ["log", "serde"], "other files are ignored");
+    }
+}
+
+mod directory {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+[package]
+name = "foo"
+version = "VERSION"
+rust-version = "1.70"
+
+[dependencies]
+serde = { version = "1.0", optional = true }
+
+[target."cfg(windows)".dependencies]
+winapi = "0.3"
+
+[features]
+std = []
+"#;
+
+    fn vendor(root: &Path, dir: &str, name: &str, vers: &str, checksum: Option<&str>) {
+        let dir = root.join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest = MANIFEST.replace("foo", name).replace("VERSION", vers);
+        std::fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+        let checksum = serde_json::json!({"files": {}, "package": checksum});
+        std::fs::write(dir.join(".cargo-checksum.json"), checksum.to_string()).unwrap();
+    }
+
+    #[test]
+    fn synthesized_versions() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let checksum = "ab".repeat(32);
+        vendor(tmp_dir.path(), "foo", "foo", "0.2.0", Some(&checksum));
+        vendor(tmp_dir.path(), "foo-0.1.0", "foo", "0.1.0", Some(&checksum));
+        vendor(tmp_dir.path(), "foo-bar", "foo-bar", "1.0.0", None);
+        vendor(tmp_dir.path(), ".hidden", "hidden", "1.0.0", None);
+        std::fs::create_dir(tmp_dir.path().join("no-manifest")).unwrap();
+
+        let index = DirectoryIndex::new(tmp_dir.path());
+        let foo = index.crate_("foo").unwrap().unwrap();
+        let versions: Vec<_> = foo.versions().iter().map(|v| v.version()).collect();
+        assert_eq!(versions, ["0.1.0", "0.2.0"], "versions are ordered by version number");
+        let version = foo.highest_version();
+        assert_eq!(version.checksum(), &[0xab; 32]);
+        assert_eq!(version.rust_version(), Some("1.70"));
+        assert_eq!(version.features().len(), 1);
+        let deps: Vec<_> = version
+            .dependencies()
+            .iter()
+            .map(|dep| {
+                (
+                    dep.name(),
+                    dep.requirement(),
+                    dep.kind(),
+                    dep.target(),
+                    dep.is_optional(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            deps,
+            [
+                ("serde", "^1.0", DependencyKind::Normal, None, true),
+                ("winapi", "^0.3", DependencyKind::Normal, Some("cfg(windows)"), false),
+            ]
+        );
+
+        let foo_bar = index.crate_("foo_bar").unwrap();
+        assert!(foo_bar.is_none(), "names must match exactly, except for their case");
+        let foo_bar = index.crate_("foo-bar").unwrap().unwrap();
+        assert_eq!(foo_bar.versions().len(), 1);
+        assert_eq!(foo_bar.versions()[0].checksum(), &[0; 32], "the checksum is unknown");
+        assert!(index.crate_("hidden").unwrap().is_none());
+
+        let crates: Vec<_> = index
+            .crates()
+            .map(|krate| (krate.name().to_owned(), krate.versions().len()))
+            .collect();
+        assert_eq!(crates, [("foo".to_owned(), 2), ("foo-bar".to_owned(), 1)]);
+    }
+
+    #[test]
+    fn invalid_checksum() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        vendor(tmp_dir.path(), "foo", "foo", "0.1.0", Some("not hex"));
+
+        let index = DirectoryIndex::new(tmp_dir.path());
+        let err = index.crate_("foo").unwrap_err();
+        assert!(matches!(err, crates_index::Error::InvalidPackage { .. }), "{err:?}");
+        assert_eq!(index.crates().count(), 0);
+    }
+}
+
+#[test]
+fn as_index() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let registry_path = tmp_dir.path().join("registry");
+    std::fs::create_dir_all(registry_path.join("index/3/l")).unwrap();
+    let krate = Crate::from_versions([version("log", "0.4.0")]).unwrap();
+    std::fs::write(registry_path.join("index/3/l/log"), krate.to_index_lines()).unwrap();
+
+    let indices: Vec<BoxedIndex> = vec![
+        LocalRegistryIndex::new(&registry_path).boxed(),
+        DirectoryIndex::new(tmp_dir.path().join("vendor")).boxed(),
+    ];
+    for index in &indices {
+        assert!(index.url().starts_with("file:///"), "{}", index.url());
+        assert!(index.index_config().unwrap().is_none());
+        assert!(
+            index.last_updated("log").unwrap().is_none(),
+            "local sources aren't updated"
+        );
+    }
+    assert_eq!(indices[0].crate_("log").unwrap().unwrap(), krate);
+    assert_eq!(indices[0].crates().unwrap().count(), 1);
+    assert!(indices[1].crate_("log").is_err(), "the vendor directory doesn't exist");
+    assert_eq!(indices[1].crates().unwrap().count(), 0);
+
+    std::fs::write(
+        registry_path.join("index/config.json"),
+        r#"{"dl":"https://example.com/dl"}"#,
+    )
+    .unwrap();
+    assert_eq!(indices[0].index_config().unwrap().unwrap().dl, "https://example.com/dl");
+}
+
+#[test]
+fn any_index_from_source() {
+    use crates_index::cargo_config::Source;
+    use crates_index::AnyIndex;
+
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let index = AnyIndex::from_source(&Source::LocalRegistry(tmp_dir.path().into())).unwrap();
+    assert!(matches!(&index, AnyIndex::LocalRegistry(index) if index.path() == tmp_dir.path()));
+    let index = AnyIndex::from_source(&Source::Directory(tmp_dir.path().into())).unwrap();
+    assert!(matches!(&index, AnyIndex::Directory(_)));
+    assert_eq!(index.crates().unwrap().count(), 0);
+}
@@ -0,0 +1,176 @@
+use crates_index::package::{checksum, version_from_package, version_from_package_with_registry};
+use crates_index::{Crate, DependencyKind};
+
+/// Create a `.crate` file containing only `manifest` at `path`, like `foo-0.1.0/Cargo.toml`.
+fn package(path: &str, manifest: &str) -> Vec<u8> {
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        Vec::new(),
+        flate2::Compression::default(),
+    ));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, manifest.as_bytes()).unwrap();
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+const MANIFEST: &str = r#"
+[package]
+name = "foo"
+version = "0.1.0"
+links = "foo"
+rust-version = "1.70"
+
+[dependencies.serde]
+version = "1.0.100"
+features = ["derive"]
+optional = true
+
+[dependencies.json]
+version = "1"
+package = "serde_json"
+default-features = false
+
+[dependencies.other]
+version = "0.2"
+registry-index = "https://example.com/index"
+
+[dev-dependencies]
+tempfile = "3"
+
+[build-dependencies.cc]
+version = ">=1.0, <2"
+
+[target."cfg(unix)".dependencies]
+libc = "0.2"
+
+[features]
+default = ["std"]
+std = []
+derive = ["dep:serde", "serde?/derive"]
+full = ["derive"]
+"#;
+
+#[test]
+fn version_from_manifest() {
+    let crate_file = package("foo-0.1.0/Cargo.toml", MANIFEST);
+    let version = version_from_package(&crate_file).unwrap();
+
+    assert_eq!(version.name(), "foo");
+    assert_eq!(version.version(), "0.1.0");
+    assert_eq!(version.links(), Some("foo"));
+    assert_eq!(version.rust_version(), Some("1.70"));
+    assert_eq!(version.checksum(), &checksum(&crate_file));
+    assert!(!version.is_yanked());
+    assert_eq!(version.features().len(), 4);
+
+    let deps: Vec<_> = version
+        .dependencies()
+        .iter()
+        .map(|dep| (dep.name(), dep.requirement(), dep.kind(), dep.target()))
+        .collect();
+    assert_eq!(
+        deps,
+        [
+            ("cc", ">=1.0, <2", DependencyKind::Build, None),
+            ("json", "^1", DependencyKind::Normal, None),
+            ("libc", "^0.2", DependencyKind::Normal, Some("cfg(unix)")),
+            ("other", "^0.2", DependencyKind::Normal, None),
+            ("serde", "^1.0.100", DependencyKind::Normal, None),
+            ("tempfile", "^3", DependencyKind::Dev, None),
+        ],
+        "dependencies are sorted by name"
+    );
+
+    let json = &version.dependencies()[1];
+    assert_eq!(json.package(), Some("serde_json"));
+    assert_eq!(json.crate_name(), "serde_json");
+    assert!(!json.has_default_features());
+    let serde = &version.dependencies()[4];
+    assert!(serde.is_optional());
+    assert_eq!(serde.features(), ["derive"]);
+    assert_eq!(serde.registry(), None, "crates.io is the registry published to");
+    assert_eq!(
+        version.dependencies()[3].registry(),
+        Some("https://example.com/index"),
+        "dependencies of other registries name their index"
+    );
+}
+
+#[test]
+fn index_line() {
+    let crate_file = package("foo-0.1.0/Cargo.toml", MANIFEST);
+    let version = version_from_package(&crate_file).unwrap();
+    let line = version.to_index_line();
+    assert!(!line.contains('\n'));
+
+    let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(
+        json["features"],
+        serde_json::json!({"default": ["std"], "full": ["derive"], "std": []}),
+        "features in the original format stay in `features`, even if they enable those using the new syntax"
+    );
+    assert_eq!(
+        json["features2"],
+        serde_json::json!({"derive": ["dep:serde", "serde?/derive"]}),
+        "features using the new syntax are moved to `features2`"
+    );
+    assert_eq!(json["v"], 2);
+    assert_eq!(json["cksum"], hex::encode(checksum(&crate_file)));
+    assert_eq!(json["deps"][0]["kind"], "build");
+    assert_eq!(json["deps"][1]["target"], serde_json::Value::Null);
+
+    let parsed = Crate::from_slice(line.as_bytes()).unwrap();
+    let parsed = parsed.highest_version();
+    assert_eq!(parsed.features(), version.features());
+    assert_eq!(parsed.to_index_line(), line, "the line round-trips");
+}
+
+#[test]
+fn other_registry() {
+    let crate_file = package("foo-0.1.0/Cargo.toml", MANIFEST);
+    let version = version_from_package_with_registry(&crate_file, "https://example.com/index/").unwrap();
+    let registries: Vec<_> = version.dependencies().iter().map(|dep| dep.registry()).collect();
+    assert_eq!(
+        registries,
+        [
+            Some("https://github.com/rust-lang/crates.io-index"),
+            Some("https://github.com/rust-lang/crates.io-index"),
+            Some("https://github.com/rust-lang/crates.io-index"),
+            None,
+            Some("https://github.com/rust-lang/crates.io-index"),
+            Some("https://github.com/rust-lang/crates.io-index"),
+        ]
+    );
+}
+
+#[test]
+fn invalid_packages() {
+    let err = version_from_package(b"not a tarball").unwrap_err();
+    assert!(matches!(err, crates_index::Error::Io(_)), "{err:?}");
+
+    let err = version_from_package(&package("foo-0.1.0/src/lib.rs", "")).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "The package is invalid: there is no Cargo.toml in the package"
+    );
+
+    let err = version_from_package(&package("foo-0.2.0/Cargo.toml", MANIFEST)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "The package is invalid: the manifest of foo is in 'foo-0.2.0' instead of 'foo-0.1.0'"
+    );
+
+    let manifest = "[package]\nname = \"foo\"\nversion = \"0.1.0\"\n[dependencies]\nbar = { version = \"1\", registry = \"mine\" }\n";
+    let err = version_from_package(&package("foo-0.1.0/Cargo.toml", manifest)).unwrap_err();
+    assert!(matches!(err, crates_index::Error::InvalidPackage { .. }), "{err:?}");
+
+    let manifest = "[package]\nname = \"foo\"\nversion = \"0.1.0\"\n[dependencies]\nbar = \"not a requirement\"\n";
+    let err = version_from_package(&package("foo-0.1.0/Cargo.toml", manifest)).unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("The package is invalid: the version requirement of the dependency 'bar' is invalid"),
+        "{err}"
+    );
+}
@@ -0,0 +1,158 @@
+//! A minimal HTTP/1.1 stand-in for a sparse registry, serving the files in `tests/fixtures`.
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::fixtures::{
+    AUTOCFG_ETAG, AUTOCFG_INDEX_ENTRY, CONFIG_JSON, CRATES_INDEX_INDEX_ENTRY, CRATES_INDEX_LAST_MODIFIED,
+};
+
+pub const CONFIG_ETAG: &str = "\"62d0e9b3-2d1\"";
+
+/// A request as seen by the server.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub struct FixtureServer {
+    /// The URL of the index, including the `sparse+` prefix.
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+    concurrency: Arc<Concurrency>,
+}
+
+impl FixtureServer {
+    /// All requests received so far, in order.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The highest amount of requests that were handled at the same time.
+    pub fn max_concurrent_requests(&self) -> usize {
+        self.concurrency.max.load(Ordering::SeqCst)
+    }
+}
+
+/// The amount of requests being handled, and its high-water mark.
+#[derive(Default)]
+struct Concurrency {
+    current: AtomicUsize,
+    max: AtomicUsize,
+}
+
+/// Start a server on a random local port which lives until the end of the test process.
+pub fn spawn() -> FixtureServer {
+    spawn_with_delay(Duration::ZERO)
+}
+
+/// Like [`spawn()`], but wait `delay` before responding to each request, so concurrent requests overlap.
+pub fn spawn_with_delay(delay: Duration) -> FixtureServer {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("sparse+http://{}/", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let concurrency = Arc::new(Concurrency::default());
+    std::thread::spawn({
+        let requests = requests.clone();
+        let concurrency = concurrency.clone();
+        move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                let requests = requests.clone();
+                let concurrency = concurrency.clone();
+                std::thread::spawn(move || handle(stream, &requests, &concurrency, delay));
+            }
+        }
+    });
+    FixtureServer {
+        url,
+        requests,
+        concurrency,
+    }
+}
+
+fn handle(stream: TcpStream, requests: &Mutex<Vec<Request>>, concurrency: &Concurrency, delay: Duration) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    if reader.read_line(&mut line).is_err() {
+        return;
+    }
+    let path = line.split_whitespace().nth(1).unwrap_or_default().to_owned();
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+    let request = Request { path, headers };
+    requests.lock().unwrap().push(request.clone());
+
+    // Count the request as handled until right before responding, as the client may send the next one right after
+    let current = concurrency.current.fetch_add(1, Ordering::SeqCst) + 1;
+    concurrency.max.fetch_max(current, Ordering::SeqCst);
+    std::thread::sleep(delay);
+    concurrency.current.fetch_sub(1, Ordering::SeqCst);
+
+    let (status, extra_headers, body) = respond(&request);
+    let mut stream = stream;
+    let mut head = format!(
+        "HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n",
+        body.len()
+    );
+    for (key, value) in extra_headers {
+        head.push_str(&format!("{key}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(&body);
+}
+
+fn respond(request: &Request) -> (&'static str, Vec<(&'static str, String)>, Vec<u8>) {
+    match request.path.as_str() {
+        "/config.json" => {
+            if request.header("if-none-match") == Some(CONFIG_ETAG) {
+                return ("304 Not Modified", vec![("etag", CONFIG_ETAG.into())], Vec::new());
+            }
+            ("200 OK", vec![("etag", CONFIG_ETAG.into())], CONFIG_JSON.to_vec())
+        }
+        "/au/to/autocfg" => {
+            if request.header("if-none-match") == Some(AUTOCFG_ETAG) {
+                return ("304 Not Modified", vec![("etag", AUTOCFG_ETAG.into())], Vec::new());
+            }
+            (
+                "200 OK",
+                vec![("etag", AUTOCFG_ETAG.into()), ("content-encoding", "gzip".into())],
+                gzip(AUTOCFG_INDEX_ENTRY),
+            )
+        }
+        "/cr/at/crates-index" => (
+            "200 OK",
+            vec![("last-modified", CRATES_INDEX_LAST_MODIFIED.into())],
+            CRATES_INDEX_INDEX_ENTRY.to_vec(),
+        ),
+        "/un/av/unavailable" => ("503 Service Unavailable", vec![("retry-after", "0".into())], Vec::new()),
+        _ => ("404 Not Found", vec![], Vec::new()),
+    }
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
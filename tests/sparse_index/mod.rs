@@ -15,6 +15,159 @@ fn crate_from_cache() {
     assert_eq!(crate_.highest_version().version(), "1.1.0");
 }
 
+#[test]
+fn as_index() {
+    use crates_index::Index;
+
+    let index = crates_index::SparseIndex::with_path(
+        std::path::Path::new(&std::env::var_os("CARGO_MANIFEST_DIR").unwrap())
+            .join("tests/fixtures/sparse_registry_cache/cargo_home"),
+        crates_index::sparse::URL,
+    )
+    .unwrap();
+    let index: &dyn Index = &index;
+
+    assert_eq!(index.url(), crates_index::sparse::URL);
+    assert_eq!(index.crate_("autocfg").unwrap().unwrap().versions().len(), 13);
+    assert!(index.last_updated("autocfg").unwrap().is_some());
+    assert!(index.crate_("not-fetched").unwrap().is_none());
+    assert!(index.last_updated("not-fetched").unwrap().is_none());
+    assert!(index.crate_("").unwrap().is_none());
+    assert!(index.crates().is_none(), "sparse registries can't be enumerated");
+}
+
+#[test]
+fn any_index() {
+    use crates_index::{AnyIndex, Error, Index};
+
+    let index = AnyIndex::from_url("sparse+https://example.com/index/").unwrap();
+    assert!(matches!(&index, AnyIndex::Sparse(_)));
+    assert_eq!(index.url(), "sparse+https://example.com/index/");
+
+    let err = AnyIndex::from_registry_name("crates-index-test-unknown").err().unwrap();
+    assert!(
+        matches!(&err, Error::UnknownRegistry { name } if name == "crates-index-test-unknown"),
+        "{err:?}"
+    );
+}
+
+/// Temporary indices and cache entries shared by the tests below.
+#[cfg(feature = "sparse")]
+mod fixtures {
+    use crates_index::SparseIndex;
+    use http::header;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+
+    // curl -v -H 'accept-encoding: gzip,identity' -H 'if-none-match: W/"aa975a09419f9c8f61762a3d06fdb67d"' https://index.crates.io/au/to/autocfg
+    // as of 2023-06-15
+    pub const AUTOCFG_INDEX_ENTRY: &[u8] = include_bytes!("../../tests/fixtures/autocfg.txt");
+    pub const CRATES_INDEX_INDEX_ENTRY: &[u8] = include_bytes!("../../tests/fixtures/crates-index.txt");
+    // curl -v -H 'accept-encoding: gzip,identity' https://index.crates.io/config.json
+    pub const CONFIG_JSON: &[u8] = include_bytes!("../../tests/fixtures/config.json");
+    pub const AUTOCFG_ETAG: &str = "W/\"5f15de4a723e10b3f9eaf048d693cccc\"";
+    pub const CRATES_INDEX_LAST_MODIFIED: &str = "Thu, 15 Jun 2023 10:12:45 GMT";
+    pub const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    /// An empty index of the registry at `url` in a temporary directory, which is removed along with the guard.
+    pub fn tmp_index(url: &str) -> (tempfile::TempDir, SparseIndex) {
+        let dir = tempfile::tempdir().unwrap();
+        let index = SparseIndex::with_path(dir.path(), url).unwrap();
+        (dir, index)
+    }
+
+    /// An empty index of crates.io in a temporary directory.
+    pub fn crates_io_tmp() -> (tempfile::TempDir, SparseIndex) {
+        tmp_index(crates_index::sparse::URL)
+    }
+
+    /// An index of crates.io with cache entries of the `names` among `autocfg`, which has an etag,
+    /// and `crates-index`, which has a last-modified date.
+    pub fn index_with_cached(names: &[&str]) -> (tempfile::TempDir, SparseIndex) {
+        let (dir, index) = crates_io_tmp();
+        for name in names {
+            let (body, (key, value)) = match *name {
+                "autocfg" => (AUTOCFG_INDEX_ENTRY, (header::ETAG, AUTOCFG_ETAG)),
+                "crates-index" => (
+                    CRATES_INDEX_INDEX_ENTRY,
+                    (header::LAST_MODIFIED, CRATES_INDEX_LAST_MODIFIED),
+                ),
+                _ => unreachable!("there is no fixture for '{name}'"),
+            };
+            let response = http::Response::builder()
+                .status(http::StatusCode::OK)
+                .header(key, value)
+                .body(body.to_vec())
+                .unwrap();
+            index.parse_cache_response(name, response, true).unwrap();
+        }
+        (dir, index)
+    }
+
+    /// An index with cache entries of `autocfg` and `crates-index`, and a broken one named `junk`.
+    pub fn index_with_two_cached_crates() -> (tempfile::TempDir, SparseIndex) {
+        let (dir, index) = index_with_cached(&["autocfg", "crates-index"]);
+        write_junk(&index);
+        (dir, index)
+    }
+
+    /// An index with `autocfg` used an hour ago, `crates-index` used two hours ago and a broken entry.
+    pub fn index_with_aged_entries() -> (tempfile::TempDir, SparseIndex) {
+        let (dir, index) = index_with_two_cached_crates();
+        set_age(&cache_path(&index, "autocfg"), HOUR);
+        set_age(&cache_path(&index, "crates-index"), 2 * HOUR);
+        (dir, index)
+    }
+
+    /// An index of a registry whose `config.json` says if `auth_required`, with a token set.
+    pub fn private_index(auth_required: bool) -> (tempfile::TempDir, SparseIndex) {
+        let (dir, mut index) = tmp_index("sparse+https://registry.example.com/index/");
+        std::fs::create_dir_all(index.path()).unwrap();
+        std::fs::write(
+            index.path().join("config.json"),
+            format!(r#"{{"dl":"https://registry.example.com/dl","auth-required":{auth_required}}}"#),
+        )
+        .unwrap();
+        index.set_auth_token(Some("Bearer secret".into()));
+        (dir, index)
+    }
+
+    /// An empty index of crates.io which remembers missing crates for `ttl`.
+    pub fn index_with_ttl(ttl: Option<Duration>) -> (tempfile::TempDir, SparseIndex) {
+        let (dir, mut index) = crates_io_tmp();
+        index.set_negative_cache_ttl(ttl);
+        (dir, index)
+    }
+
+    /// The path of the cache entry of the crate `name`, which has at least four characters.
+    pub fn cache_path(index: &SparseIndex, name: &str) -> PathBuf {
+        index
+            .path()
+            .join(".cache")
+            .join(&name[..2])
+            .join(&name[2..4])
+            .join(name)
+    }
+
+    /// Write a cache entry named `junk` which can't be read, and return its path.
+    pub fn write_junk(index: &SparseIndex) -> PathBuf {
+        let junk = cache_path(index, "junk");
+        std::fs::create_dir_all(junk.parent().unwrap()).unwrap();
+        std::fs::write(&junk, b"\x01not a cache entry").unwrap();
+        junk
+    }
+
+    /// Make the file at `path` look like it was last modified `age` ago.
+    pub fn set_age(path: &Path, age: Duration) {
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+}
+
 #[cfg(all(test, feature = "sparse"))]
 mod with_sparse_http_feature {
     use crates_index::SparseIndex;
@@ -72,13 +225,10 @@ mod with_sparse_http_feature {
     }
 
     mod parse_cache_response {
+        use crate::sparse_index::fixtures::AUTOCFG_INDEX_ENTRY;
         use crate::sparse_index::with_sparse_http_feature::crates_io;
         use http::header;
 
-        // curl -v -H 'accept-encoding: gzip,identity' -H 'if-none-match: W/"aa975a09419f9c8f61762a3d06fdb67d"' https://index.crates.io/au/to/autocfg
-        // as of 2023-06-15
-        const AUTOCFG_INDEX_ENTRY: &[u8] = include_bytes!("../../tests/fixtures/autocfg.txt");
-
         // Validates that a response with the full index contents are properly parsed
         #[test]
         fn modified_response() {
@@ -123,6 +273,76 @@ mod with_sparse_http_feature {
 
             assert!(index.parse_cache_response("serde", response, false).unwrap().is_none());
         }
+
+        fn error_for(status: u16, retry_after: Option<&str>) -> crates_index::Error {
+            let mut response = http::Response::builder().status(status);
+            if let Some(value) = retry_after {
+                response = response.header(header::RETRY_AFTER, value);
+            }
+            crates_io()
+                .parse_cache_response("serde", response.body(Vec::new()).unwrap(), false)
+                .unwrap_err()
+        }
+
+        #[test]
+        fn unauthorized_response() {
+            assert!(matches!(
+                error_for(401, None),
+                crates_index::Error::Unauthorized { crate_name: Some(name) } if name == "serde"
+            ));
+        }
+
+        #[test]
+        fn rate_limited_response() {
+            match error_for(429, Some("120")) {
+                crates_index::Error::HttpStatus {
+                    status,
+                    crate_name,
+                    retry_after,
+                    retryable,
+                } => {
+                    assert_eq!(status, 429);
+                    assert_eq!(crate_name.as_deref(), Some("serde"));
+                    assert_eq!(retry_after, Some(std::time::Duration::from_secs(120)));
+                    assert!(retryable);
+                }
+                err => panic!("unexpected error: {err:?}"),
+            }
+        }
+
+        #[test]
+        fn server_error_response_with_retry_date() {
+            match error_for(503, Some("Wed, 21 Oct 2015 07:28:00 GMT")) {
+                crates_index::Error::HttpStatus {
+                    retry_after, retryable, ..
+                } => {
+                    assert_eq!(
+                        retry_after,
+                        Some(std::time::Duration::ZERO),
+                        "dates in the past mean 'now'"
+                    );
+                    assert!(retryable);
+                }
+                err => panic!("unexpected error: {err:?}"),
+            }
+        }
+
+        #[test]
+        fn client_error_response_is_not_retryable() {
+            match error_for(403, Some("garbage")) {
+                crates_index::Error::HttpStatus {
+                    status,
+                    retry_after,
+                    retryable,
+                    ..
+                } => {
+                    assert_eq!(status, 403);
+                    assert_eq!(retry_after, None);
+                    assert!(!retryable);
+                }
+                err => panic!("unexpected error: {err:?}"),
+            }
+        }
     }
 
     #[test]
@@ -147,18 +367,705 @@ mod with_sparse_http_feature {
         assert_eq!(req.headers().get(header::ACCEPT).unwrap(), "text/plain");
     }
 
-    mod parse_config_response {
+    mod auth {
+        use crate::sparse_index::fixtures::private_index;
+        use crates_index::sparse::cargo_token;
+        use http::{header, Request};
+
+        #[test]
+        fn token_is_sent_if_auth_is_required() {
+            let (_dir, index) = private_index(true);
+            assert!(index.index_config().unwrap().auth_required);
+
+            let req: Request<Vec<u8>> = index.make_cache_request("serde").unwrap().body(vec![]).unwrap();
+            assert_eq!(req.headers().get(header::AUTHORIZATION).unwrap(), "Bearer secret");
+            assert!(
+                !format!("{index:?}").contains("secret"),
+                "the token isn't leaked in debug output"
+            );
+        }
+
+        #[test]
+        fn token_is_only_sent_for_config_if_auth_is_not_required() {
+            let (_dir, index) = private_index(false);
+
+            let req: Request<Vec<u8>> = index.make_cache_request("serde").unwrap().body(vec![]).unwrap();
+            assert!(req.headers().get(header::AUTHORIZATION).is_none());
+
+            let req: Request<Vec<u8>> = index.make_config_request().unwrap().body(vec![]).unwrap();
+            assert_eq!(req.headers().get(header::AUTHORIZATION).unwrap(), "Bearer secret");
+        }
+
+        #[test]
+        fn configuration_is_read_once_until_a_new_one_is_written() {
+            let (_dir, index) = private_index(true);
+            let authorization = |index: &crates_index::SparseIndex| {
+                let req: Request<Vec<u8>> = index.make_cache_request("serde").unwrap().body(vec![]).unwrap();
+                req.headers().get(header::AUTHORIZATION).is_some()
+            };
+            assert!(authorization(&index));
+
+            let public = br#"{"dl":"https://registry.example.com/dl","auth-required":false}"#;
+            std::fs::write(index.path().join("config.json"), public).unwrap();
+            assert!(authorization(&index), "the configuration isn't read again");
+
+            let response = http::Response::builder().status(200).body(public.to_vec()).unwrap();
+            index.parse_config_response(response, true).unwrap();
+            assert!(!authorization(&index), "the written configuration is used");
+        }
+
+        #[test]
+        #[serial_test::serial]
+        fn cargo_token_from_credentials_and_environment() {
+            let cargo_home = tempfile::tempdir().unwrap();
+            assert_eq!(cargo_token(Some("my-corp"), Some(cargo_home.path())).unwrap(), None);
+
+            std::fs::write(
+                cargo_home.path().join("credentials.toml"),
+                "[registry]\ntoken = \"crates-io-token\"\n[registries.my-corp]\ntoken = \"file-token\"\n",
+            )
+            .unwrap();
+            assert_eq!(
+                cargo_token(Some("my-corp"), Some(cargo_home.path()))
+                    .unwrap()
+                    .as_deref(),
+                Some("file-token")
+            );
+            assert_eq!(
+                cargo_token(Some("crates-io"), Some(cargo_home.path()))
+                    .unwrap()
+                    .as_deref(),
+                Some("crates-io-token")
+            );
+
+            std::fs::write(
+                cargo_home.path().join("credentials"),
+                "[registries.my-corp]\ntoken = \"legacy-token\"\n",
+            )
+            .unwrap();
+            assert_eq!(
+                cargo_token(Some("my-corp"), Some(cargo_home.path()))
+                    .unwrap()
+                    .as_deref(),
+                Some("legacy-token"),
+                "like cargo, the legacy file is preferred"
+            );
+            assert_eq!(
+                cargo_token(None, Some(cargo_home.path())).unwrap(),
+                None,
+                "only one credentials file is read"
+            );
+
+            std::fs::write(
+                cargo_home.path().join("config.toml"),
+                "[registries.other]\nindex = \"sparse+https://example.com/\"\ntoken = \"config-token\"\n",
+            )
+            .unwrap();
+            assert_eq!(
+                cargo_token(Some("other"), Some(cargo_home.path())).unwrap().as_deref(),
+                Some("config-token"),
+                "tokens may be set in the configuration as well"
+            );
+
+            std::env::set_var("CARGO_REGISTRIES_MY_CORP_TOKEN", "env-token");
+            let token = cargo_token(Some("my-corp"), Some(cargo_home.path())).unwrap();
+            std::env::remove_var("CARGO_REGISTRIES_MY_CORP_TOKEN");
+            assert_eq!(token.as_deref(), Some("env-token"), "the environment has precedence");
+        }
+    }
+
+    mod cached_crates {
+        use crate::sparse_index::fixtures::{
+            crates_io_tmp, index_with_two_cached_crates, AUTOCFG_ETAG, CRATES_INDEX_LAST_MODIFIED,
+        };
+
+        fn expected() -> Vec<(String, String)> {
+            vec![
+                ("autocfg".into(), format!("etag: {AUTOCFG_ETAG}")),
+                (
+                    "crates-index".into(),
+                    format!("last-modified: {CRATES_INDEX_LAST_MODIFIED}"),
+                ),
+            ]
+        }
+
+        #[test]
+        fn iterates_all_valid_entries() {
+            let (_dir, index) = index_with_two_cached_crates();
+            let mut found: Vec<_> = index
+                .cached_crates()
+                .map(|(krate, version)| (krate.name().to_owned(), version))
+                .collect();
+            found.sort();
+            assert_eq!(found, expected());
+        }
+
+        #[test]
+        #[cfg(feature = "parallel")]
+        fn iterates_all_valid_entries_in_parallel() {
+            use rayon::iter::ParallelIterator;
+            let (_dir, index) = index_with_two_cached_crates();
+            let mut found: Vec<_> = index
+                .cached_crates_parallel()
+                .map(|(krate, version)| (krate.name().to_owned(), version))
+                .collect();
+            found.sort();
+            assert_eq!(found, expected());
+        }
+
+        #[test]
+        fn empty_without_cache() {
+            let (_dir, index) = crates_io_tmp();
+            assert_eq!(index.cached_crates().count(), 0);
+        }
+    }
+
+    mod cache_entry_info {
+        use crate::sparse_index::fixtures::{cache_path, index_with_cached, set_age, HOUR};
+        use std::time::Duration;
+
+        #[test]
+        fn reads_validator_and_versions() {
+            let (_dir, index) = index_with_cached(&["autocfg"]);
+            let info = index.cache_entry_info("autocfg").unwrap();
+            assert_eq!(info.version(), "etag: W/\"5f15de4a723e10b3f9eaf048d693cccc\"");
+            assert_eq!(info.etag(), Some("W/\"5f15de4a723e10b3f9eaf048d693cccc\""));
+            assert_eq!(info.last_modified(), None);
+            assert_eq!(info.cache_version(), 3);
+            assert_eq!(info.index_format_version(), Some(2));
+            assert!(info.is_current());
+            assert!(info.age() < Duration::from_secs(60));
+        }
+
+        #[test]
+        fn missing_entry() {
+            let (_dir, index) = index_with_cached(&["autocfg"]);
+            let err = index.cache_entry_info("serde").unwrap_err();
+            assert!(matches!(err, crates_index::Error::Io(err) if err.kind() == std::io::ErrorKind::NotFound));
+            assert!(index
+                .crate_from_cache_if_fresh("serde", Duration::from_secs(60))
+                .unwrap()
+                .is_none());
+        }
+
+        #[test]
+        fn only_fresh_entries_are_returned() {
+            let (_dir, index) = index_with_cached(&["autocfg"]);
+            let max_age = Duration::from_secs(5 * 60);
+            assert!(index.crate_from_cache_if_fresh("autocfg", max_age).unwrap().is_some());
+
+            set_age(&cache_path(&index, "autocfg"), HOUR);
+            assert!(index.cache_entry_info("autocfg").unwrap().age() >= Duration::from_secs(60 * 60));
+            assert!(index.crate_from_cache_if_fresh("autocfg", max_age).unwrap().is_none());
+        }
+
+        #[test]
+        fn not_modified_response_refreshes_entry() {
+            let (_dir, index) = index_with_cached(&["autocfg"]);
+            set_age(&cache_path(&index, "autocfg"), HOUR);
+            let not_modified = || {
+                http::Response::builder()
+                    .status(http::StatusCode::NOT_MODIFIED)
+                    .body(Vec::new())
+                    .unwrap()
+            };
+
+            index.parse_cache_response("autocfg", not_modified(), false).unwrap();
+            assert!(index.cache_entry_info("autocfg").unwrap().age() >= Duration::from_secs(60 * 60));
+
+            index.parse_cache_response("autocfg", not_modified(), true).unwrap();
+            assert!(index.cache_entry_info("autocfg").unwrap().age() < Duration::from_secs(60));
+        }
+    }
+
+    mod gc {
+        use crate::sparse_index::fixtures::{cache_path, index_with_aged_entries, set_age, HOUR};
+        use crates_index::sparse::{GcPolicy, GcReason};
+
+        fn removed(report: &crates_index::sparse::GcReport) -> Vec<(String, GcReason)> {
+            report
+                .removed
+                .iter()
+                .map(|entry| {
+                    (
+                        entry.path.file_name().unwrap().to_string_lossy().into_owned(),
+                        entry.reason,
+                    )
+                })
+                .collect()
+        }
+
+        #[test]
+        fn default_policy_removes_nothing() {
+            let (_dir, index) = index_with_aged_entries();
+            let report = index.gc(GcPolicy::default()).unwrap();
+            assert!(report.removed.is_empty());
+            assert_eq!(report.remaining_entries, 3);
+        }
+
+        #[test]
+        fn expired_and_unsupported_entries() {
+            let (_dir, index) = index_with_aged_entries();
+            let report = index
+                .gc(GcPolicy::default().max_age(HOUR + HOUR / 2).remove_unsupported(true))
+                .unwrap();
+            let mut removed = removed(&report);
+            removed.sort_by(|a, b| a.0.cmp(&b.0));
+            assert_eq!(
+                removed,
+                [
+                    ("crates-index".into(), GcReason::Expired),
+                    ("junk".into(), GcReason::Unsupported)
+                ]
+            );
+            assert_eq!(report.remaining_entries, 1);
+            assert!(!cache_path(&index, "crates-index").exists());
+            assert!(
+                !index.path().join(".cache/ju").exists(),
+                "empty directories are removed"
+            );
+            assert!(index.crate_from_cache("autocfg").is_ok());
+        }
+
+        #[test]
+        fn size_limit_removes_least_recently_used_first() {
+            let (_dir, index) = index_with_aged_entries();
+            let size = |name| std::fs::metadata(cache_path(&index, name)).unwrap().len();
+            let (autocfg, crates_index, junk) = (size("autocfg"), size("crates-index"), size("junk"));
+            set_age(&cache_path(&index, "junk"), 3 * HOUR);
+
+            let report = index
+                .gc(GcPolicy::default().max_total_size(autocfg + crates_index))
+                .unwrap();
+            assert_eq!(removed(&report), [("junk".into(), GcReason::SizeLimit)]);
+            assert_eq!(report.removed_bytes(), junk);
+            assert_eq!(report.remaining_bytes, autocfg + crates_index);
+
+            let report = index.gc(GcPolicy::default().max_total_size(autocfg)).unwrap();
+            assert_eq!(removed(&report), [("crates-index".into(), GcReason::SizeLimit)]);
+            assert_eq!(report.remaining_entries, 1);
+        }
+
+        #[test]
+        fn dry_run_removes_nothing() {
+            let (_dir, index) = index_with_aged_entries();
+            let report = index.gc(GcPolicy::default().max_total_size(0).dry_run(true)).unwrap();
+            assert!(report.dry_run);
+            assert_eq!(report.removed.len(), 3);
+            assert_eq!(report.remaining_entries, 0);
+            assert_eq!(index.cached_crates().count(), 2, "the valid entries are still there");
+        }
+    }
+
+    mod verify_cache {
+        use crate::sparse_index::fixtures::{cache_path, index_with_cached};
+        use crates_index::cache::CacheProblemKind;
+        use crates_index::SparseIndex;
+
+        fn write(index: &SparseIndex, rel_path: &str, bytes: &[u8]) {
+            let path = index.path().join(".cache").join(rel_path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, bytes).unwrap();
+        }
+
+        fn entry(cache_version: u8, index_format_version: u32, entries: &[(&str, &str)]) -> Vec<u8> {
+            let mut bytes = vec![cache_version];
+            bytes.extend_from_slice(&index_format_version.to_le_bytes());
+            bytes.extend_from_slice(b"etag: W/\"1\"\0");
+            for (version, line) in entries {
+                bytes.extend_from_slice(version.as_bytes());
+                bytes.push(0);
+                bytes.extend_from_slice(line.as_bytes());
+                bytes.push(0);
+            }
+            bytes
+        }
+
+        #[test]
+        fn reports_and_repairs_broken_entries() {
+            let (_dir, index) = index_with_cached(&["autocfg"]);
+            let valid = std::fs::read(cache_path(&index, "autocfg")).unwrap();
+
+            let line = r#"{"name":"a","vers":"1.0.0","deps":[],"features":{},"cksum":"0000000000000000000000000000000000000000000000000000000000000000"}"#;
+            write(&index, "1/a", &entry(3, 2, &[("1.0.0", line)]));
+            write(&index, "1/b", &valid[..valid.len() / 2]);
+            write(&index, "1/c", &entry(2, 2, &[]));
+            write(&index, "1/d", &entry(3, 1, &[]));
+            write(&index, "1/e", &entry(3, 2, &[("0.1.0", "{not json}")]));
+            write(&index, "se/rd/serde", &valid);
+            write(&index, "au/to/.autocfg.1234-0.tmp", &valid[..valid.len() / 2]);
+
+            let report = index.verify_cache(false).unwrap();
+            assert_eq!(report.checked, 7, "temporary files of concurrent writers are ignored");
+            assert!(!report.repaired);
+            let mut problems: Vec<_> = report
+                .problems
+                .iter()
+                .map(|problem| {
+                    (
+                        problem.path.strip_prefix(index.path()).unwrap().to_owned(),
+                        problem.kind.clone(),
+                    )
+                })
+                .collect();
+            problems.sort_by(|a, b| a.0.cmp(&b.0));
+            let path = |rel_path: &str| std::path::Path::new(".cache").join(rel_path);
+            assert_eq!(problems.len(), 5);
+            assert_eq!(problems[0], (path("1/b"), CacheProblemKind::Truncated));
+            assert_eq!(
+                problems[1],
+                (path("1/c"), CacheProblemKind::UnsupportedCacheVersion { version: 2 })
+            );
+            assert_eq!(
+                problems[2],
+                (
+                    path("1/d"),
+                    CacheProblemKind::UnsupportedIndexFormatVersion { version: Some(1) }
+                )
+            );
+            assert!(matches!(
+                &problems[3],
+                (p, CacheProblemKind::InvalidJson { version, line, .. }) if *p == path("1/e") && version == "0.1.0" && line == "{not json}"
+            ));
+            assert_eq!(
+                problems[4],
+                (
+                    path("se/rd/serde"),
+                    CacheProblemKind::NameMismatch {
+                        expected: "serde".into(),
+                        found: "autocfg".into()
+                    }
+                )
+            );
+
+            let report = index.verify_cache(true).unwrap();
+            assert!(report.repaired);
+            assert_eq!(report.problems.len(), 5);
+            let report = index.verify_cache(false).unwrap();
+            assert!(report.is_ok(), "{report:?}");
+            assert_eq!(report.checked, 2, "only valid entries are left");
+            assert!(index.path().join(".cache/au/to/.autocfg.1234-0.tmp").is_file());
+        }
+    }
+
+    mod negative_cache {
+        use crate::sparse_index::fixtures::{index_with_ttl, AUTOCFG_INDEX_ENTRY};
         use crates_index::{Error, SparseIndex};
-        use std::io;
+        use std::time::Duration;
 
-        // curl -v -H 'accept-encoding: gzip,identity' https://index.crates.io/config.json
-        const CONFIG_JSON: &[u8] = include_bytes!("../../tests/fixtures/config.json");
+        fn respond(index: &SparseIndex, name: &str, status: u16, write_cache_entry: bool) -> bool {
+            let body = if status == 200 {
+                AUTOCFG_INDEX_ENTRY.to_vec()
+            } else {
+                Vec::new()
+            };
+            let response = http::Response::builder().status(status).body(body).unwrap();
+            index
+                .parse_cache_response(name, response, write_cache_entry)
+                .unwrap()
+                .is_some()
+        }
 
-        fn crates_io_tmp() -> (tempfile::TempDir, SparseIndex) {
-            let dir = tempfile::tempdir().unwrap();
-            let index = SparseIndex::with_path(dir.path(), crates_index::sparse::URL).unwrap();
-            (dir, index)
+        #[test]
+        fn missing_crates_are_remembered() {
+            let (_dir, index) = index_with_ttl(Some(Duration::from_secs(60)));
+            assert!(!respond(&index, "autocfg", 404, true));
+
+            assert!(index.is_known_missing("autocfg"));
+            assert!(index.is_known_missing("AutoCfg"), "names are case-insensitive");
+            assert!(matches!(
+                index.make_cache_request("autocfg"),
+                Err(Error::KnownMissing { crate_name }) if crate_name == "autocfg"
+            ));
+            assert!(matches!(
+                index.crate_from_cache("autocfg"),
+                Err(Error::KnownMissing { .. })
+            ));
+            assert!(index
+                .crate_from_cache_if_fresh("autocfg", Duration::from_secs(60))
+                .unwrap()
+                .is_none());
+
+            assert!(respond(&index, "autocfg", 200, true));
+            assert!(!index.is_known_missing("autocfg"), "the crate was published after all");
+            assert!(index.make_cache_request("autocfg").is_ok());
+        }
+
+        #[test]
+        fn entries_expire() {
+            let (_dir, mut index) = index_with_ttl(Some(Duration::from_secs(60)));
+            assert!(!respond(&index, "autocfg", 410, true));
+            assert!(index.is_known_missing("autocfg"));
+
+            index.set_negative_cache_ttl(Some(Duration::ZERO));
+            std::thread::sleep(Duration::from_millis(10));
+            assert!(!index.is_known_missing("autocfg"));
+        }
+
+        #[test]
+        fn nothing_is_recorded_unless_enabled_and_writing() {
+            let (_dir, mut index) = index_with_ttl(None);
+            assert!(!respond(&index, "autocfg", 404, true));
+            index.set_negative_cache_ttl(Some(Duration::from_secs(60)));
+            assert!(
+                !index.is_known_missing("autocfg"),
+                "it was disabled when the response was parsed"
+            );
+
+            assert!(!respond(&index, "autocfg", 404, false));
+            assert!(!index.is_known_missing("autocfg"), "writing the cache wasn't permitted");
+
+            assert!(!respond(&index, "autocfg", 404, true));
+            index.set_negative_cache_ttl(None);
+            assert!(!index.is_known_missing("autocfg"), "entries are ignored while disabled");
+        }
+    }
+
+    mod retry_policy {
+        use crates_index::sparse::RetryPolicy;
+        use crates_index::{CargoConfig, Error};
+        use std::time::Duration;
+
+        fn status(status: u16, retry_after: Option<Duration>) -> Error {
+            Error::HttpStatus {
+                status,
+                crate_name: None,
+                retry_after,
+                retryable: status == 429 || status >= 500,
+            }
+        }
+
+        #[test]
+        fn backoff_is_exponential_and_capped() {
+            let policy = RetryPolicy::default()
+                .jitter(false)
+                .max_retries(10)
+                .initial_delay(Duration::from_secs(1))
+                .max_delay(Duration::from_secs(5));
+            let delays: Vec<_> = (0..4)
+                .map(|retries| policy.retry_delay(retries, &status(500, None)).unwrap())
+                .collect();
+            assert_eq!(
+                delays,
+                [1, 2, 4, 5].map(Duration::from_secs),
+                "doubling until the maximum is reached"
+            );
+        }
+
+        #[test]
+        fn jitter_stays_within_half_of_the_backoff() {
+            let policy = RetryPolicy::default().initial_delay(Duration::from_secs(2));
+            for _ in 0..100 {
+                let delay = policy.backoff(0);
+                assert!(
+                    delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2),
+                    "{delay:?}"
+                );
+            }
+        }
+
+        #[test]
+        fn retries_are_capped_like_cargo_net_retry() {
+            let policy = RetryPolicy::default();
+            assert_eq!(policy.retries(), 3);
+            assert!(policy.retry_delay(2, &status(503, None)).is_some());
+            assert!(policy.retry_delay(3, &status(503, None)).is_none());
+            assert!(RetryPolicy::never().retry_delay(0, &status(503, None)).is_none());
+
+            let mut config = CargoConfig::default();
+            assert_eq!(RetryPolicy::from_cargo_config(&config), policy);
+            config.net.retry = Some(5);
+            assert_eq!(RetryPolicy::from_cargo_config(&config).retries(), 5);
+            config.net.retry = Some(0);
+            assert_eq!(RetryPolicy::from_cargo_config(&config), RetryPolicy::never());
+        }
+
+        #[test]
+        fn only_temporary_errors_are_retried() {
+            let policy = RetryPolicy::default();
+            assert!(policy.retry_delay(0, &status(404, None)).is_none());
+            assert!(policy
+                .retry_delay(0, &Error::Unauthorized { crate_name: None })
+                .is_none());
+            assert!(policy
+                .retry_delay(0, &std::io::Error::from(std::io::ErrorKind::TimedOut).into())
+                .is_some());
+            assert!(policy
+                .retry_delay(0, &std::io::Error::from(std::io::ErrorKind::NotFound).into())
+                .is_none());
+            assert!(
+                policy
+                    .retry_delay(0, &std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into())
+                    .is_none(),
+                "truncated cache entries aren't fixed by retrying"
+            );
+        }
+
+        #[test]
+        fn retry_after_is_honored() {
+            let policy = RetryPolicy::default();
+            assert_eq!(
+                policy.retry_delay(0, &status(429, Some(Duration::from_secs(30)))),
+                Some(Duration::from_secs(30))
+            );
+            assert_eq!(
+                policy.retry_delay(0, &status(503, Some(Duration::from_secs(3600)))),
+                Some(Duration::from_secs(60)),
+                "servers can't make clients wait for arbitrarily long"
+            );
+            assert_eq!(
+                policy
+                    .max_retry_after(Duration::from_secs(5))
+                    .retry_delay(0, &status(503, Some(Duration::from_secs(30)))),
+                Some(Duration::from_secs(5))
+            );
+        }
+    }
+
+    mod crawler {
+        use crate::sparse_index::fixtures::crates_io_tmp;
+        use crates_index::sparse::Crawler;
+        use crates_index::DependencyKind;
+
+        fn line(name: &str, vers: &str, deps: &[(&str, &str)], yanked: bool) -> String {
+            let deps: Vec<_> = deps
+                .iter()
+                .map(|(dep, kind)| {
+                    format!(
+                        r#"{{"name":"{dep}","req":"^1","features":[],"optional":false,"default_features":true,"kind":"{kind}"}}"#
+                    )
+                })
+                .collect();
+            format!(
+                r#"{{"name":"{name}","vers":"{vers}","deps":[{}],"features":{{}},"cksum":"1234567890123456789012345678901234567890123456789012345678901234","yanked":{yanked}}}"#,
+                deps.join(",")
+            )
+        }
+
+        fn entry(name: &str) -> Option<String> {
+            Some(match name {
+                "app" => line("app", "1.0.0", &[("lib", "normal"), ("tester", "dev")], false),
+                "lib" => [
+                    line("lib", "1.0.0", &[("Helper", "build")], false),
+                    line("lib", "1.1.0", &[("old", "normal")], true),
+                ]
+                .join("\n"),
+                "helper" => line("helper", "1.0.0", &[("lib", "normal"), ("gone", "normal")], false),
+                "tester" => line("tester", "1.0.0", &[], false),
+                "old" => line("old", "1.0.0", &[], false),
+                _ => return None,
+            })
+        }
+
+        fn crawl(mut crawler: Crawler) -> (Vec<String>, Crawler) {
+            let (_dir, index) = crates_io_tmp();
+            let mut requested = Vec::new();
+            while !crawler.is_done() {
+                let batch = crawler.next_requests(&index, 2).unwrap();
+                assert!(!batch.is_empty() && batch.len() <= 2);
+                for (name, _request) in batch {
+                    let response = match entry(&name.to_ascii_lowercase()) {
+                        Some(body) => http::Response::builder().status(200).body(body.into_bytes()),
+                        None => http::Response::builder().status(404).body(Vec::new()),
+                    }
+                    .unwrap();
+                    crawler.parse_response(&index, &name, response, false).unwrap();
+                    requested.push(name);
+                }
+            }
+            (requested, crawler)
+        }
+
+        #[test]
+        fn follows_normal_and_build_dependencies_of_unyanked_versions() {
+            let (requested, crawler) = crawl(Crawler::new(["app"]));
+            assert_eq!(requested, ["app", "lib", "Helper", "gone"]);
+            let names: Vec<_> = crawler.crates().iter().map(|c| c.name()).collect();
+            assert_eq!(names, ["app", "lib", "helper"]);
+            assert_eq!(crawler.missing(), ["gone"]);
+        }
+
+        #[test]
+        fn dependency_kinds_and_yanked_versions_are_configurable() {
+            let (requested, _) = crawl(
+                Crawler::new(["app"])
+                    .dependency_kinds(&[DependencyKind::Normal, DependencyKind::Dev])
+                    .include_yanked(true),
+            );
+            assert_eq!(requested, ["app", "lib", "tester", "old"]);
+        }
+
+        #[test]
+        fn crates_added_from_elsewhere_are_not_requested() {
+            let mut crawler = Crawler::new(["app", "lib"]);
+            crawler.add_crate(crates_index::Crate::from_slice(entry("lib").unwrap().as_bytes()).unwrap());
+            let (requested, crawler) = crawl(crawler);
+            assert_eq!(requested, ["app", "Helper", "gone"]);
+            assert_eq!(crawler.crates().len(), 3);
+        }
+
+        #[test]
+        fn temporary_errors_are_retried_and_others_reported() {
+            let (_dir, index) = crates_io_tmp();
+            let mut crawler = Crawler::new(["tester", "old"]);
+            let respond = |crawler: &mut Crawler, name: &str, status: u16| {
+                let body = if status == 200 {
+                    entry(name).unwrap().into_bytes()
+                } else {
+                    Vec::new()
+                };
+                let response = http::Response::builder().status(status).body(body).unwrap();
+                crawler
+                    .parse_response(&index, name, response, false)
+                    .map(|krate| krate.is_some())
+            };
+
+            let names: Vec<_> = crawler
+                .next_requests(&index, 10)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.0)
+                .collect();
+            assert_eq!(names, ["tester", "old"]);
+            assert!(respond(&mut crawler, "tester", 503).unwrap_err().is_retryable());
+            assert!(!respond(&mut crawler, "old", 400).unwrap_err().is_retryable());
+            assert!(
+                !crawler.is_done(),
+                "the crate with a temporary error is requested again"
+            );
+
+            let names: Vec<_> = crawler
+                .next_requests(&index, 10)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.0)
+                .collect();
+            assert_eq!(names, ["tester"]);
+            assert!(respond(&mut crawler, "tester", 200).unwrap());
+            assert!(crawler.is_done());
+            assert_eq!(crawler.crates().len(), 1);
+            assert_eq!(crawler.failed(), ["old"], "permanent errors are reported");
+        }
+
+        #[test]
+        fn crates_added_again_replace_the_previous_ones() {
+            let mut crawler = Crawler::new(["lib"]);
+            let krate = crates_index::Crate::from_slice(entry("lib").unwrap().as_bytes()).unwrap();
+            crawler.add_crate(krate.clone());
+            crawler.add_crate(krate);
+            let (_, crawler) = crawl(crawler);
+            assert_eq!(
+                crawler.crates().iter().filter(|krate| krate.name() == "lib").count(),
+                1,
+                "crates are only listed once"
+            );
         }
+    }
+
+    mod parse_config_response {
+        use crate::sparse_index::fixtures::{crates_io_tmp, CONFIG_JSON};
+        use crates_index::Error;
+        use std::io;
 
         fn make_response() -> http::Response<Vec<u8>> {
             http::Response::builder()
@@ -199,5 +1106,605 @@ mod with_sparse_http_feature {
             assert_eq!(config.dl, stored_config.dl);
             assert_eq!(config.api, stored_config.api);
         }
+
+        #[test]
+        fn revalidation() {
+            let (_dir, index) = crates_io_tmp();
+            assert!(index.try_index_config().unwrap().is_none());
+            let request = index.make_config_request().unwrap().body(()).unwrap();
+            assert!(request.headers().get(http::header::IF_NONE_MATCH).is_none());
+
+            let response = http::Response::builder()
+                .status(http::StatusCode::OK)
+                .header(http::header::ETAG, "\"abc\"")
+                .body(CONFIG_JSON.to_vec())
+                .unwrap();
+            index.parse_config_response(response, true).unwrap();
+            assert!(index.try_index_config().unwrap().is_some());
+
+            let request = index.make_config_request().unwrap().body(()).unwrap();
+            assert_eq!(request.headers()[http::header::IF_NONE_MATCH], "\"abc\"");
+
+            let not_modified = http::Response::builder()
+                .status(http::StatusCode::NOT_MODIFIED)
+                .body(Vec::new())
+                .unwrap();
+            let config = index.parse_config_response(not_modified, true).unwrap();
+            assert_eq!(config.dl, "https://static.crates.io/crates", "read from disk");
+
+            index.parse_config_response(make_response(), true).unwrap();
+            let request = index.make_config_request().unwrap().body(()).unwrap();
+            assert!(
+                request.headers().get(http::header::IF_NONE_MATCH).is_none(),
+                "the validator is forgotten if the latest response has none"
+            );
+        }
+
+        #[test]
+        fn not_modified_without_config_on_disk() {
+            let (_dir, index) = crates_io_tmp();
+            let not_modified = http::Response::builder()
+                .status(http::StatusCode::NOT_MODIFIED)
+                .body(Vec::new())
+                .unwrap();
+            assert!(matches!(
+                index.parse_config_response(not_modified, false),
+                Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound
+            ));
+        }
+
+        #[test]
+        fn errors() {
+            let (_dir, index) = crates_io_tmp();
+            let response = |status: u16| http::Response::builder().status(status).body(Vec::new()).unwrap();
+
+            assert!(matches!(
+                index.parse_config_response(response(404), false),
+                Err(Error::ConfigNotFound)
+            ));
+            assert!(matches!(
+                index.parse_config_response(response(401), false),
+                Err(Error::Unauthorized { crate_name: None })
+            ));
+            assert!(matches!(
+                index.parse_config_response(response(502), false),
+                Err(Error::HttpStatus {
+                    status: 502,
+                    crate_name: None,
+                    retry_after: None,
+                    retryable: true
+                })
+            ));
+        }
+    }
+
+    mod export {
+        use crate::sparse_index::fixtures::{AUTOCFG_INDEX_ENTRY, CONFIG_JSON};
+        use crates_index::sparse::{ExportReport, Exporter, IndexDir};
+        use crates_index::{Crate, IndexConfig};
+        use std::path::Path;
+
+        fn config() -> IndexConfig {
+            serde_json::from_slice(CONFIG_JSON).unwrap()
+        }
+
+        fn krate(name: &str, versions: &[&str]) -> Crate {
+            let lines: Vec<_> = versions
+                .iter()
+                .map(|version| {
+                    format!(
+                        r#"{{"name":"{name}","vers":"{version}","deps":[],"cksum":"{}","features":{{}},"yanked":false}}"#,
+                        "00".repeat(32)
+                    )
+                })
+                .collect();
+            Crate::from_slice(lines.join("\n").as_bytes()).unwrap()
+        }
+
+        fn crates() -> Vec<Crate> {
+            vec![
+                Crate::from_slice(AUTOCFG_INDEX_ENTRY).unwrap(),
+                krate("a", &["1.0.0"]),
+                krate("abc", &["0.1.0", "0.2.0"]),
+                krate("Foo-Bar", &["1.0.0"]),
+            ]
+        }
+
+        #[test]
+        fn writes_the_sparse_layout() {
+            let dir = tempfile::tempdir().unwrap();
+            let report = Exporter::new(dir.path()).export(&config(), crates()).unwrap();
+            assert_eq!(
+                report,
+                ExportReport {
+                    written: 4,
+                    unchanged: 0,
+                    removed: Vec::new(),
+                    config_written: true,
+                }
+            );
+
+            for (rel_path, versions) in [("au/to/autocfg", 13), ("1/a", 1), ("3/a/abc", 2), ("fo/o-/foo-bar", 1)] {
+                let krate = Crate::new(dir.path().join(rel_path)).unwrap();
+                assert_eq!(krate.versions().len(), versions, "{rel_path}");
+            }
+            let written: IndexConfig =
+                serde_json::from_slice(&std::fs::read(dir.path().join("config.json")).unwrap()).unwrap();
+            assert_eq!(written.dl, config().dl);
+        }
+
+        #[test]
+        fn incremental_export_only_writes_changes() {
+            let dir = tempfile::tempdir().unwrap();
+            let exporter = Exporter::new(dir.path());
+            exporter.export(&config(), crates()).unwrap();
+            let modified = |rel_path: &str| {
+                std::fs::metadata(dir.path().join(rel_path))
+                    .unwrap()
+                    .modified()
+                    .unwrap()
+            };
+            let autocfg_modified = modified("au/to/autocfg");
+
+            let mut changed = crates();
+            changed[1] = krate("a", &["1.0.0", "1.1.0"]);
+            let report = exporter.export(&config(), changed.clone()).unwrap();
+            assert_eq!((report.written, report.unchanged, report.config_written), (1, 3, false));
+            assert_eq!(
+                modified("au/to/autocfg"),
+                autocfg_modified,
+                "unchanged files aren't touched"
+            );
+            assert_eq!(Crate::new(dir.path().join("1/a")).unwrap().versions().len(), 2);
+
+            let report = exporter.clone().incremental(false).export(&config(), changed).unwrap();
+            assert_eq!((report.written, report.unchanged, report.config_written), (4, 0, true));
+        }
+
+        #[test]
+        fn remove_stale() {
+            let dir = tempfile::tempdir().unwrap();
+            let exporter = Exporter::new(dir.path());
+            exporter.export(&config(), crates()).unwrap();
+            for unrelated in [".git/au/to/autocfg", "README.md", "3/a/abc.bak"] {
+                let path = dir.path().join(unrelated);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, b"").unwrap();
+            }
+
+            let mut remaining = crates();
+            remaining.truncate(2);
+            let report = exporter.export(&config(), remaining.clone()).unwrap();
+            assert!(report.removed.is_empty(), "stale files are kept by default");
+
+            let report = exporter
+                .clone()
+                .remove_stale(true)
+                .export(&config(), remaining)
+                .unwrap();
+            let mut removed: Vec<_> = report
+                .removed
+                .iter()
+                .map(|path| path.strip_prefix(dir.path()).unwrap().to_owned())
+                .collect();
+            removed.sort();
+            assert_eq!(removed, [Path::new("3/a/abc"), Path::new("fo/o-/foo-bar")]);
+            assert!(!dir.path().join("fo").exists(), "empty directories are removed");
+            for kept in [".git/au/to/autocfg", "README.md", "3/a/abc.bak", "au/to/autocfg", "1/a"] {
+                assert!(dir.path().join(kept).is_file(), "{kept}");
+            }
+
+            assert!(exporter.remove_crate("autocfg").unwrap());
+            assert!(!exporter.remove_crate("autocfg").unwrap());
+            assert!(!dir.path().join("au").exists());
+        }
+
+        #[test]
+        fn export_from_copies_files_as_they_are() {
+            let source = tempfile::tempdir().unwrap();
+            let line = format!(
+                r#"{{"name":"a","vers":"1.0.0","deps":[],"cksum":"{}","features":{{}},"yanked":false,"pubtime":"2025-01-01T00:00:00Z"}}"#,
+                "00".repeat(32)
+            );
+            std::fs::create_dir_all(source.path().join("1")).unwrap();
+            std::fs::write(source.path().join("1/a"), &line).unwrap();
+            std::fs::write(source.path().join("config.json"), CONFIG_JSON).unwrap();
+            let source = IndexDir::new(source.path());
+
+            let dir = tempfile::tempdir().unwrap();
+            let exporter = Exporter::new(dir.path());
+            let report = exporter.export_from(&source, ["A", "missing"]).unwrap();
+            assert_eq!(
+                report,
+                ExportReport {
+                    written: 1,
+                    unchanged: 0,
+                    removed: Vec::new(),
+                    config_written: true,
+                }
+            );
+            assert_eq!(
+                std::fs::read_to_string(dir.path().join("1/a")).unwrap(),
+                line,
+                "fields unknown to this crate are kept"
+            );
+            assert_eq!(std::fs::read(dir.path().join("config.json")).unwrap(), CONFIG_JSON);
+
+            assert!(!exporter.copy_crate(&source, "a").unwrap(), "unchanged");
+            assert!(!exporter.copy_crate(&source, "missing").unwrap());
+            exporter.write_crate(&krate("a", &["1.0.0"])).unwrap();
+            assert!(exporter.copy_crate(&source, "a").unwrap());
+            assert_eq!(std::fs::read_to_string(dir.path().join("1/a")).unwrap(), line);
+        }
+    }
+
+    mod server {
+        use crate::sparse_index::fixtures::{tmp_index, AUTOCFG_INDEX_ENTRY, CONFIG_JSON};
+        use crates_index::sparse::{IndexDir, MemoryIndex, SparseServer};
+        use crates_index::{Crate, IndexConfig, SparseIndex};
+        use http::{header, StatusCode};
+
+        fn memory_server() -> SparseServer<MemoryIndex> {
+            let config: IndexConfig = serde_json::from_slice(CONFIG_JSON).unwrap();
+            let autocfg = Crate::from_slice(AUTOCFG_INDEX_ENTRY).unwrap();
+            SparseServer::new(MemoryIndex::with_crates(&config, [autocfg])).path_prefix("/index/")
+        }
+
+        fn client_index() -> (tempfile::TempDir, SparseIndex) {
+            tmp_index("sparse+http://localhost/index/")
+        }
+
+        fn get(server: &SparseServer<MemoryIndex>, path: &str) -> http::Response<Vec<u8>> {
+            server.handle(&http::Request::get(path).body(()).unwrap()).unwrap()
+        }
+
+        #[test]
+        fn round_trip_with_sparse_index() {
+            let server = memory_server();
+            let (_dir, index) = client_index();
+
+            let request = index.make_config_request().unwrap().body(()).unwrap();
+            let config = index
+                .parse_config_response(server.handle(&request).unwrap(), true)
+                .unwrap();
+            assert_eq!(config.dl, "https://static.crates.io/crates");
+
+            let request = index.make_cache_request("autocfg").unwrap().body(()).unwrap();
+            let response = server.handle(&request).unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain");
+            let krate = index.parse_cache_response("autocfg", response, true).unwrap().unwrap();
+            assert_eq!(krate.versions().len(), 13);
+
+            let request = index.make_cache_request("autocfg").unwrap().body(()).unwrap();
+            assert!(request.headers().contains_key(header::IF_NONE_MATCH));
+            let response = server.handle(&request).unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "the etag is stable");
+            let krate = index.parse_cache_response("autocfg", response, true).unwrap().unwrap();
+            assert_eq!(krate.versions().len(), 13, "read from the cache");
+
+            let request = index.make_config_request().unwrap().body(()).unwrap();
+            let response = server.handle(&request).unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+            let request = index.make_cache_request("missing").unwrap().body(()).unwrap();
+            let response = server.handle(&request).unwrap();
+            assert!(index.parse_cache_response("missing", response, true).unwrap().is_none());
+        }
+
+        #[test]
+        fn etags() {
+            let server = memory_server();
+            let response = get(&server, "/index/au/to/autocfg");
+            let etag = response.headers()[header::ETAG].to_str().unwrap().to_owned();
+            assert!(etag.starts_with('"') && etag.ends_with('"'), "{etag} is a strong etag");
+
+            for if_none_match in [
+                etag.clone(),
+                format!("W/{etag}"),
+                format!("\"other\", {etag}"),
+                "*".into(),
+            ] {
+                let request = http::Request::get("/index/au/to/autocfg")
+                    .header(header::IF_NONE_MATCH, if_none_match)
+                    .body(())
+                    .unwrap();
+                let response = server.handle(&request).unwrap();
+                assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+                assert_eq!(response.headers()[header::ETAG], etag.as_str());
+                assert!(response.body().is_empty());
+            }
+
+            let request = http::Request::get("/index/au/to/autocfg")
+                .header(header::IF_NONE_MATCH, "\"other\"")
+                .body(())
+                .unwrap();
+            assert_eq!(server.handle(&request).unwrap().status(), StatusCode::OK);
+        }
+
+        #[test]
+        fn paths() {
+            let server = memory_server();
+            assert_eq!(get(&server, "/index/config.json").status(), StatusCode::OK);
+            assert_eq!(
+                get(&server, "/index/config.json").headers()[header::CONTENT_TYPE],
+                "application/json"
+            );
+            assert_eq!(get(&server, "/index/AU/to/AutoCfg").status(), StatusCode::OK);
+            for path in [
+                "/config.json",
+                "/au/to/autocfg",
+                "/index/autocfg",
+                "/index/au/autocfg",
+                "/index/se/rd/autocfg",
+                "/index/au/to/../to/autocfg",
+                "/index/au/to/autocfg/",
+                "/index/",
+                "/index/.cache/au/to/autocfg",
+            ] {
+                assert_eq!(get(&server, path).status(), StatusCode::NOT_FOUND, "{path}");
+            }
+        }
+
+        #[test]
+        fn methods() {
+            let server = memory_server();
+            let response = server
+                .handle(&http::Request::head("/index/au/to/autocfg").body(()).unwrap())
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers()[header::CONTENT_LENGTH],
+                get(&server, "/index/au/to/autocfg").body().len().to_string().as_str()
+            );
+            assert!(response.body().is_empty());
+
+            let response = server
+                .handle(&http::Request::put("/index/au/to/autocfg").body(()).unwrap())
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+            assert_eq!(response.headers()[header::ALLOW], "GET, HEAD");
+        }
+
+        #[test]
+        fn memory_index() {
+            let mut server = memory_server();
+            assert_eq!(server.source().len(), 1);
+            let krate = Crate::from_slice(&get(&server, "/index/au/to/autocfg").into_body()).unwrap();
+            assert_eq!(krate.versions().len(), 13);
+            assert_eq!(
+                krate.highest_version().checksum(),
+                Crate::from_slice(AUTOCFG_INDEX_ENTRY)
+                    .unwrap()
+                    .highest_version()
+                    .checksum()
+            );
+
+            assert!(server.source_mut().remove("AutoCfg"));
+            assert!(server.source().is_empty());
+            assert_eq!(get(&server, "/index/au/to/autocfg").status(), StatusCode::NOT_FOUND);
+        }
+
+        #[test]
+        fn index_dir() {
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::create_dir_all(dir.path().join("au/to")).unwrap();
+            std::fs::write(dir.path().join("au/to/autocfg"), AUTOCFG_INDEX_ENTRY).unwrap();
+            std::fs::write(dir.path().join("config.json"), CONFIG_JSON).unwrap();
+            let server = SparseServer::new(IndexDir::new(dir.path()));
+
+            let response = server
+                .handle(&http::Request::get("/au/to/autocfg").body(()).unwrap())
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.body(), AUTOCFG_INDEX_ENTRY, "files are served as they are");
+
+            let response = server
+                .handle(&http::Request::get("/config.json").body(()).unwrap())
+                .unwrap();
+            assert_eq!(response.body(), CONFIG_JSON);
+
+            let response = server
+                .handle(&http::Request::get("/se/rd/serde").body(()).unwrap())
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+}
+
+#[cfg(any(feature = "sparse-ureq", feature = "sparse-async"))]
+mod fixture_server;
+
+#[cfg(feature = "sparse-ureq")]
+mod fetch_with_ureq {
+    use crate::sparse_index::fixture_server::{self, FixtureServer};
+    use crate::sparse_index::fixtures::{tmp_index, AUTOCFG_ETAG};
+    use crates_index::SparseIndex;
+
+    fn local_index() -> (tempfile::TempDir, FixtureServer, SparseIndex) {
+        let server = fixture_server::spawn();
+        let (dir, index) = tmp_index(&server.url);
+        (dir, server, index)
+    }
+
+    #[test]
+    fn fetch_crate_decompresses_writes_cache_and_revalidates() {
+        let (_dir, server, index) = local_index();
+
+        let krate = index.fetch_crate("autocfg").unwrap().expect("crate exists");
+        assert_eq!(krate.versions().len(), 13);
+        assert_eq!(krate.highest_version().version(), "1.1.0");
+        assert_eq!(
+            index.crate_from_cache("autocfg").unwrap().versions().len(),
+            13,
+            "the cache entry was written"
+        );
+
+        let krate = index.fetch_crate("autocfg").unwrap().expect("read from cache");
+        assert_eq!(krate.versions().len(), 13);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].header("if-none-match"), None);
+        assert_eq!(
+            requests[1].header("if-none-match"),
+            Some(AUTOCFG_ETAG),
+            "the second request is conditional and answered with 304"
+        );
+    }
+
+    #[test]
+    fn fetch_missing_crate() {
+        let (_dir, _server, index) = local_index();
+        assert!(index.fetch_crate("serde").unwrap().is_none());
+    }
+
+    #[test]
+    fn fetch_skips_known_missing_crates() {
+        let (_dir, server, mut index) = local_index();
+        index.set_negative_cache_ttl(Some(std::time::Duration::from_secs(60)));
+
+        assert!(index.fetch_crate("serde").unwrap().is_none());
+        assert!(index.fetch_crate("serde").unwrap().is_none());
+        assert_eq!(
+            server.requests().len(),
+            1,
+            "the second lookup is answered by the negative cache"
+        );
+    }
+
+    #[test]
+    fn fetch_retries_temporary_failures() {
+        let (_dir, server, mut index) = local_index();
+        index.set_retry_policy(crates_index::sparse::RetryPolicy::default().max_retries(2));
+
+        let err = index.fetch_crate("unavailable").unwrap_err();
+        assert!(matches!(err, crates_index::Error::HttpStatus { status: 503, .. }));
+        assert_eq!(server.requests().len(), 3, "the first attempt and two retries");
+
+        assert!(index.fetch_crate("serde").unwrap().is_none());
+        assert_eq!(server.requests().len(), 4, "a missing crate isn't a failure");
+    }
+
+    #[test]
+    fn fetch_config() {
+        let (_dir, server, index) = local_index();
+        let config = index.fetch_config().unwrap();
+        assert_eq!(config.dl, "https://static.crates.io/crates");
+        assert_eq!(index.index_config().unwrap().dl, config.dl, "it was written to disk");
+
+        let config = index.fetch_config().unwrap();
+        assert_eq!(config.dl, "https://static.crates.io/crates");
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[1].header("if-none-match"),
+            Some(fixture_server::CONFIG_ETAG),
+            "the second request is conditional and answered with 304"
+        );
+    }
+}
+
+#[cfg(feature = "sparse-async")]
+mod fetch_async {
+    use crate::sparse_index::fixture_server;
+    use crate::sparse_index::fixtures::{tmp_index, CRATES_INDEX_LAST_MODIFIED};
+    use crates_index::SparseIndex;
+    use futures_util::StreamExt;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn fetch_crates_with_bounded_concurrency() {
+        let server = fixture_server::spawn_with_delay(std::time::Duration::from_millis(50));
+        let (_dir, index) = tmp_index(&server.url);
+
+        let results: HashMap<_, _> = index
+            .fetch_crates_async(["autocfg", "crates-index", "serde", "a", "b", "c"], 2)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect();
+
+        assert_eq!(results.len(), 6);
+        assert_eq!(
+            server.max_concurrent_requests(),
+            2,
+            "requests are concurrent, but never more than allowed"
+        );
+        assert_eq!(
+            results["autocfg"].as_ref().unwrap().as_ref().unwrap().versions().len(),
+            13,
+            "gzip was decoded"
+        );
+        assert!(results["crates-index"].as_ref().unwrap().is_some());
+        assert!(
+            results["serde"].as_ref().unwrap().is_none(),
+            "the server doesn't know it"
+        );
+
+        assert!(index.crate_from_cache("autocfg").is_ok());
+        assert!(index.crate_from_cache("crates-index").is_ok());
+
+        index.fetch_crate_async("crates-index").await.unwrap().unwrap();
+        let last = server.requests().pop().unwrap();
+        assert_eq!(
+            last.header("if-modified-since"),
+            Some(CRATES_INDEX_LAST_MODIFIED),
+            "cache entries carry the same version as written by the blocking path"
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_retries_temporary_failures() {
+        let server = fixture_server::spawn();
+        let (_dir, mut index) = tmp_index(&server.url);
+        index.set_retry_policy(crates_index::sparse::RetryPolicy::default().max_retries(1));
+
+        let err = index.fetch_crate_async("unavailable").await.unwrap_err();
+        assert!(err.is_retryable());
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn waiting_for_the_cache_lock_does_not_block_the_runtime() {
+        use crates_index::lock::{LockMode, PackageCacheLock};
+        use std::time::Duration;
+
+        let server = fixture_server::spawn();
+        let cargo_home = tempfile::tempdir().unwrap();
+        let index = SparseIndex::with_path(cargo_home.path(), &server.url).unwrap();
+
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let holder = std::thread::spawn({
+            let cargo_home = cargo_home.path().to_owned();
+            move || {
+                let _lock = PackageCacheLock::acquire(&cargo_home, LockMode::DownloadExclusive).unwrap();
+                locked_tx.send(()).unwrap();
+                let _ = release_rx.recv_timeout(Duration::from_secs(5));
+            }
+        });
+        locked_rx.recv().unwrap();
+
+        let mut fetch = Box::pin(index.fetch_crate_async("autocfg"));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(300), &mut fetch)
+                .await
+                .is_err(),
+            "the timer fires while the fetch waits for the lock"
+        );
+        release_tx.send(()).unwrap();
+        holder.join().unwrap();
+        assert!(fetch.await.unwrap().is_some());
+        assert!(index.crate_from_cache("autocfg").is_ok());
+    }
+
+    #[tokio::test]
+    async fn fetch_config() {
+        let server = fixture_server::spawn();
+        let (_dir, index) = tmp_index(&server.url);
+
+        let config = index.fetch_config_async().await.unwrap();
+        assert_eq!(index.index_config().unwrap().dl, config.dl);
     }
 }